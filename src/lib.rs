@@ -1,5 +1,6 @@
 pub mod types;
 pub mod processor;
+pub mod render;
 pub mod steps;
 
 // Include generated protobuf code
@@ -10,6 +11,6 @@ pub mod proto {
 }
 
 // Re-export commonly used types
-pub use types::{Config, Strategy, ProcessError, QueryOutput};
+pub use types::{Config, Strategy, ProcessError, QueryOutput, PdfRendererKind, TableFormat, RowSampling};
 pub use processor::Processor;
 pub use steps::*; 
\ No newline at end of file
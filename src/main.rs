@@ -79,6 +79,15 @@ enum Commands {
     },
 }
 
+/// Escape text for an HTML element's content or a quoted attribute
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
 fn generate_html(query: &Query) -> String {
     let mut html = String::from(r#"<!DOCTYPE html>
 <html>
@@ -233,7 +242,18 @@ fn generate_html(query: &Query) -> String {
             }
             html.push_str("</div>");
         }
-        
+
+        if !meta.properties.is_empty() {
+            html.push_str("<div class='label'>Properties:</div><div class='value'>");
+            let mut properties: Vec<_> = meta.properties.iter().collect();
+            properties.sort();
+            for (key, value) in properties {
+                // Keys and values come from the document itself
+                html.push_str(&format!("<div>{}: {}</div>", html_escape(key), html_escape(value)));
+            }
+            html.push_str("</div>");
+        }
+
         html.push_str("</div></div>");
     }
 
@@ -327,6 +347,7 @@ async fn main() -> Result<()> {
                     original_file_size: std::fs::metadata(&input)?.len() as i64,
                     errors: Vec::new(),
                     steps: Vec::new(),
+                    properties: Default::default(),
                 }),
             };
             
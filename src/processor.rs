@@ -1,4 +1,9 @@
 use std::path::Path;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
 use image::{DynamicImage, imageops::FilterType};
 use image::ImageEncoder;
 use crate::proto::processor::Query;
@@ -6,7 +11,14 @@ use crate::types::{ProcessError, Strategy, Config};
 use async_trait::async_trait;
 use regex::Regex;
 use lazy_static::lazy_static;
-use tracing::{trace, warn, info};
+use leptess::capi;
+use tracing::{debug, trace, warn, info};
+
+/// Minimum Tesseract OSD confidence before a page is rotated
+const OSD_MIN_CONFIDENCE: f32 = 2.0;
+
+/// Number of lines at the top and bottom of a page checked for running headers and footers
+const EDGE_LINES: usize = 3;
 
 lazy_static! {
     static ref WHITESPACE_RE: Regex = Regex::new(r"[ \t]+").unwrap();
@@ -14,6 +26,7 @@ lazy_static! {
     static ref REPEATED_DOTS_RE: Regex = Regex::new(r"[.:]{3,}").unwrap();
     static ref REPEATED_DASHES_RE: Regex = Regex::new(r"[_-]{2,}").unwrap();
     static ref MULTIPLE_NEWLINES_RE: Regex = Regex::new(r"\n\s*\n").unwrap();
+    static ref DIGITS_RE: Regex = Regex::new(r"\d+").unwrap();
 }
 
 /// Clean and normalize text content
@@ -46,6 +59,110 @@ pub fn clean_text(text: &str) -> String {
     text.trim().to_string()
 }
 
+/// Tidy text whose line layout carries structure, such as Markdown headings, lists and tables
+///
+/// Unlike `clean_text`, indentation, tabs and table rules are kept; only trailing spaces and
+/// extra blank lines are dropped.
+pub fn clean_structured_text(text: &str) -> String {
+    let text = text
+        .replace("\r\n", "\n")
+        .replace("\r", "\n");
+    let text = text
+        .split('\n')
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = MULTIPLE_NEWLINES_RE.replace_all(&text, "\n\n");
+    text.trim_matches('\n').to_string()
+}
+
+/// Remove running headers, footers and page numbers repeated across pages
+///
+/// Lines near the top or bottom of each page are compared with digits masked
+/// out, so "Page 3 of 40" matches "Page 4 of 40". A line is dropped when it
+/// appears on at least half of the pages (and on at least three). Returns one
+/// example of each removed line.
+pub fn strip_repeated_lines(pages: &mut [String]) -> Vec<String> {
+    let page_count = pages.iter().filter(|page| !page.trim().is_empty()).count();
+    if page_count < 3 {
+        return Vec::new();
+    }
+    let threshold = page_count.div_ceil(2).max(3);
+
+    // Count the pages each normalized edge line appears on
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for page in pages.iter() {
+        let lines: Vec<&str> = page.lines().collect();
+        let mut seen = HashSet::new();
+        for index in edge_line_indices(&lines) {
+            let key = normalize_repeated_line(lines[index]);
+            if seen.insert(key.clone()) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let repeated: HashSet<String> = counts.into_iter()
+        .filter(|(_, count)| *count >= threshold)
+        .map(|(key, _)| key)
+        .collect();
+    if repeated.is_empty() {
+        return Vec::new();
+    }
+
+    let mut removed = Vec::new();
+    let mut removed_keys = HashSet::new();
+    for page in pages.iter_mut() {
+        let lines: Vec<&str> = page.lines().collect();
+        let edges = edge_line_indices(&lines);
+        let kept = lines.iter()
+            .enumerate()
+            .filter(|(index, line)| {
+                if !edges.contains(index) {
+                    return true;
+                }
+                let key = normalize_repeated_line(line);
+                if !repeated.contains(&key) {
+                    return true;
+                }
+                if removed_keys.insert(key) {
+                    removed.push(line.trim().to_string());
+                }
+                false
+            })
+            .map(|(_, line)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        *page = kept;
+    }
+
+    trace!("Removed {} repeated header/footer lines", removed.len());
+    removed
+}
+
+/// Indices of the first and last few non-empty lines of a page
+fn edge_line_indices(lines: &[&str]) -> HashSet<usize> {
+    let content: Vec<usize> = lines.iter()
+        .enumerate()
+        .filter(|(_, line)| !normalize_repeated_line(line).is_empty())
+        .map(|(index, _)| index)
+        .collect();
+    content.iter()
+        .take(EDGE_LINES)
+        .chain(content.iter().rev().take(EDGE_LINES))
+        .copied()
+        .collect()
+}
+
+/// Normalize a line for repeat detection, masking out numbers
+fn normalize_repeated_line(line: &str) -> String {
+    let masked = DIGITS_RE.replace_all(line, "#");
+    masked.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 /// Optimize image specifically for OCR processing
 pub fn optimize_image_for_ocr(img: &DynamicImage) -> Result<DynamicImage, ProcessError> {
     trace!("Optimizing image for OCR");
@@ -66,6 +183,128 @@ pub fn optimize_image_for_ocr(img: &DynamicImage) -> Result<DynamicImage, Proces
     Ok(optimized)
 }
 
+/// A Tesseract engine loaded with the `osd` model, for orientation detection only
+struct OrientationDetector {
+    /// Owned by this detector: created in `new`, ended and deleted once in `drop`.
+    /// Being a raw pointer it keeps the detector on the thread that created it.
+    api: *mut capi::TessBaseAPI,
+}
+
+impl OrientationDetector {
+    /// `None` when the `osd` traineddata is not installed
+    fn new() -> Option<Self> {
+        let language = CString::new("osd").ok()?;
+        // SAFETY: TessBaseAPICreate has no preconditions and returns an owned handle or null
+        let api = unsafe { capi::TessBaseAPICreate() };
+        if api.is_null() {
+            return None;
+        }
+        let detector = Self { api };
+        // SAFETY: `api` is a live handle from TessBaseAPICreate, and `language` is a
+        // NUL-terminated string that outlives the call; a null datapath means the default
+        if unsafe { capi::TessBaseAPIInit3(detector.api, ptr::null(), language.as_ptr()) } != 0 {
+            debug!("Tesseract OSD unavailable, skipping orientation detection");
+            return None;
+        }
+        Some(detector)
+    }
+
+    /// The counter-clockwise rotation in degrees (0, 90, 180 or 270) that makes
+    /// the text upright, along with Tesseract's confidence
+    fn detect(&mut self, img: &DynamicImage) -> Option<(u32, f32)> {
+        let rgb = img.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let width = c_int::try_from(width).ok()?;
+        let height = c_int::try_from(height).ok()?;
+        let bytes_per_line = width.checked_mul(3)?;
+
+        let mut orient_deg: c_int = 0;
+        let mut orient_conf: f32 = 0.0;
+        let mut script_name: *const c_char = ptr::null();
+        let mut script_conf: f32 = 0.0;
+        // SAFETY: `self.api` is live and initialised, and only this thread uses it.
+        // `rgb` holds `height` rows of `bytes_per_line` bytes and outlives both calls,
+        // and Clear drops Tesseract's reference to it before it is freed. The out
+        // pointers are valid locals; `script_name` points into the engine's own data
+        // and is never read.
+        let detected = unsafe {
+            capi::TessBaseAPISetImage(self.api, rgb.as_ptr(), width, height, 3, bytes_per_line);
+            let detected = capi::TessBaseAPIDetectOrientationScript(
+                self.api,
+                &mut orient_deg,
+                &mut orient_conf,
+                &mut script_name,
+                &mut script_conf
+            );
+            capi::TessBaseAPIClear(self.api);
+            detected
+        };
+
+        if detected == 0 {
+            trace!("Orientation detection failed");
+            return None;
+        }
+
+        trace!("Detected orientation {} (confidence {:.2})", orient_deg, orient_conf);
+        Some((orient_deg.rem_euclid(360) as u32, orient_conf))
+    }
+}
+
+impl Drop for OrientationDetector {
+    fn drop(&mut self) {
+        // SAFETY: `self.api` came from TessBaseAPICreate and is released only here
+        unsafe {
+            capi::TessBaseAPIEnd(self.api);
+            capi::TessBaseAPIDelete(self.api);
+        }
+    }
+}
+
+thread_local! {
+    /// One OSD engine per worker thread, loaded on first use; `None` once loading fails
+    static ORIENTATION_DETECTOR: RefCell<Option<Option<OrientationDetector>>> = const { RefCell::new(None) };
+}
+
+/// Detect page orientation with Tesseract OSD
+///
+/// Returns the counter-clockwise rotation in degrees (0, 90, 180 or 270) that
+/// makes the text upright, along with Tesseract's confidence. Returns `None` if
+/// the `osd` traineddata is not installed or detection fails.
+pub fn detect_orientation(img: &DynamicImage) -> Option<(u32, f32)> {
+    ORIENTATION_DETECTOR.with(|detector| {
+        detector.borrow_mut()
+            .get_or_insert_with(OrientationDetector::new)
+            .as_mut()?
+            .detect(img)
+    })
+}
+
+/// Rotate an image upright based on detected orientation
+///
+/// Returns the corrected image and the counter-clockwise rotation applied.
+pub fn correct_orientation(img: DynamicImage) -> (DynamicImage, u32) {
+    match detect_orientation(&img) {
+        Some((degrees, confidence)) if degrees != 0 && confidence >= OSD_MIN_CONFIDENCE => {
+            debug!("Rotating image {} degrees counter-clockwise", degrees);
+            match rotate_counter_clockwise(img, degrees) {
+                Ok(rotated) => (rotated, degrees),
+                Err(img) => (img, 0),
+            }
+        }
+        _ => (img, 0),
+    }
+}
+
+/// Rotate an image counter-clockwise by a right angle, handing it back unchanged for any other angle
+fn rotate_counter_clockwise(img: DynamicImage, degrees: u32) -> Result<DynamicImage, DynamicImage> {
+    match degrees {
+        90 => Ok(img.rotate270()),
+        180 => Ok(img.rotate180()),
+        270 => Ok(img.rotate90()),
+        _ => Err(img),
+    }
+}
+
 /// Select which PDF pages to process based on total count
 pub fn select_pages_to_process(total_pages: i32, _config: &Config) -> Vec<i32> {
     if total_pages <= 4 {
@@ -80,26 +319,6 @@ pub fn select_pages_to_process(total_pages: i32, _config: &Config) -> Vec<i32> {
     }
 }
 
-/// Validate and adjust spreadsheet range to prevent memory issues
-pub fn validate_sheet_range(start_row: u32, start_col: u32, end_row: u32, end_col: u32) -> (u32, u32, u32, u32) {
-    let max_rows = 1000;
-    let max_cols = 100;
-    
-    let adjusted_end_row = if end_row - start_row > max_rows {
-        start_row + max_rows
-    } else {
-        end_row
-    };
-    
-    let adjusted_end_col = if end_col - start_col > max_cols {
-        start_col + max_cols
-    } else {
-        end_col
-    };
-    
-    (start_row, start_col, adjusted_end_row, adjusted_end_col)
-}
-
 #[async_trait]
 pub trait AsyncProcessor: Send + Sync {
     async fn process(&self, query: &mut Query, config: &Config) -> Result<(), ProcessError>;
@@ -162,10 +381,40 @@ impl Processor {
     }
 }
 
+/// Record a key/value pair in the query metadata, if metadata is present
+pub fn record_metadata(query: &mut Query, key: &str, value: impl Into<String>) {
+    if let Some(metadata) = &mut query.metadata {
+        metadata.properties.insert(key.to_string(), value.into());
+    }
+}
+
+/// Record a non-fatal error in the query metadata, if metadata is present
+pub fn record_error(query: &mut Query, error: impl Into<String>) {
+    if let Some(metadata) = &mut query.metadata {
+        metadata.errors.push(error.into());
+    }
+}
+
 pub fn format_text_data(text: &str) -> String {
     format!("<EXTRACTED_DATA>{}</EXTRACTED_DATA>", text)
 }
 
+pub fn format_sheet_data(text: &str, name: &str) -> String {
+    format!("<SHEET NAME=\"{}\">{}</SHEET>", name.replace('"', "'"), text)
+}
+
+pub fn format_sheet_summary(text: &str, name: &str) -> String {
+    format!("<SHEET_SUMMARY NAME=\"{}\">{}</SHEET_SUMMARY>", name.replace('"', "'"), text)
+}
+
+pub fn format_table_data(text: &str, name: &str, sheet: &str) -> String {
+    format!("<TABLE NAME=\"{}\" SHEET=\"{}\">{}</TABLE>", name.replace('"', "'"), sheet.replace('"', "'"), text)
+}
+
+pub fn format_sheet_annotations(text: &str, name: &str) -> String {
+    format!("<SHEET_ANNOTATIONS NAME=\"{}\">{}</SHEET_ANNOTATIONS>", name.replace('"', "'"), text)
+}
+
 pub fn format_csv_data(text: &str) -> String {
     format!("<EXTRACTED_DATA>{}</EXTRACTED_DATA>", text)
 }
@@ -174,6 +423,28 @@ pub fn format_extracted_data(text: &str) -> String {
     format!("<EXTRACTED_DATA>{}</EXTRACTED_DATA>", text)
 }
 
+pub fn format_section_data(text: &str, kind: &str) -> String {
+    format!("<SECTION TYPE=\"{}\">{}</SECTION>", kind, text)
+}
+
+pub fn format_page_data(text: &str, page: u32, chapter: Option<&str>) -> String {
+    match chapter {
+        Some(chapter) => format!("<PAGE NUMBER={} CHAPTER=\"{}\">{}</PAGE>", page, chapter.replace('"', "'"), text),
+        None => format!("<PAGE NUMBER={}>{}</PAGE>", page, text),
+    }
+}
+
+pub fn format_slide_data(text: &str, slide: usize, title: Option<&str>) -> String {
+    match title {
+        Some(title) => format!("<SLIDE NUMBER={} TITLE=\"{}\">{}</SLIDE>", slide, title.replace('"', "'"), text),
+        None => format!("<SLIDE NUMBER={}>{}</SLIDE>", slide, text),
+    }
+}
+
+pub fn format_speaker_notes(text: &str) -> String {
+    format!("<SPEAKER_NOTES>{}</SPEAKER_NOTES>", text)
+}
+
 pub fn format_ocr_data(text: &str, page: u32) -> String {
     format!("<OCR PAGE={}>{}</OCR>", page, text)
 }
@@ -182,6 +453,13 @@ pub fn format_ocr_text(text: &str, page: u32) -> String {
     format!("<OCR PAGE={}>{}</OCR>", page, text)
 }
 
+pub fn format_image_ocr(text: &str, page: u32, image: usize, description: Option<&str>) -> String {
+    match description {
+        Some(description) => format!("<OCR PAGE={} IMAGE={} DESCRIPTION=\"{}\">{}</OCR>", page, image, description.replace('"', "'"), text),
+        None => format!("<OCR PAGE={} IMAGE={}>{}</OCR>", page, image, text),
+    }
+}
+
 pub fn is_mostly_garbage(text: &str) -> bool {
     // Return true if text is empty or whitespace
     let text = text.trim();
@@ -398,4 +676,51 @@ pub fn optimize_image(
     
     info!("Successfully optimized image to {} bytes", buffer.len());
     Ok((optimized, buffer))
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correct_orientation() {
+        // A red pixel on the right of a wide image ends up on top after a quarter turn counter-clockwise
+        let mut img = image::RgbImage::new(2, 1);
+        img.put_pixel(1, 0, image::Rgb([255, 0, 0]));
+        let rotated = rotate_counter_clockwise(DynamicImage::ImageRgb8(img), 90).ok().unwrap().to_rgb8();
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 0), &image::Rgb([255, 0, 0]));
+        assert!(rotate_counter_clockwise(DynamicImage::new_rgb8(2, 1), 45).is_err());
+
+        // Pages without text are left as they are, whether or not OSD is installed
+        let (blank, rotation) = correct_orientation(DynamicImage::new_rgb8(64, 32));
+        assert_eq!((blank.width(), blank.height(), rotation), (64, 32, 0));
+    }
+
+    #[test]
+    fn test_strip_repeated_lines() {
+        let bodies = ["Recitals of the agreement", "Terms and conditions", "Payment schedule", "Signatures"];
+        let mut pages: Vec<String> = bodies.iter()
+            .enumerate()
+            .map(|(i, body)| format!("ACME Corp Confidential\n{}\nPage {} of 4", body, i + 1))
+            .collect();
+
+        let removed = strip_repeated_lines(&mut pages);
+
+        assert_eq!(removed, vec!["ACME Corp Confidential".to_string(), "Page 1 of 4".to_string()]);
+        assert_eq!(pages[2], "Payment schedule");
+    }
+
+    #[test]
+    fn test_clean_structured_text() {
+        let text = "\r\n# Title  \r\n\n\n\n- item\n  - nested\n\n| a | b |\n| --- | --- |\n";
+        assert_eq!(clean_structured_text(text), "# Title\n\n- item\n  - nested\n\n| a | b |\n| --- | --- |");
+    }
+
+    #[test]
+    fn test_strip_repeated_lines_needs_three_pages() {
+        let mut pages = vec!["Header\nOne".to_string(), "Header\nTwo".to_string()];
+        assert!(strip_repeated_lines(&mut pages).is_empty());
+        assert_eq!(pages[0], "Header\nOne");
+    }
+}
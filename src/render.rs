@@ -0,0 +1,224 @@
+use std::path::Path;
+use image::DynamicImage;
+use mupdf::{Document as MuDocument, Matrix, Colorspace, Outline};
+use poppler::PopplerDocument;
+use crate::types::{ProcessError, PdfRendererKind};
+
+/// An opened document that can report its pages, render them and extract text
+pub trait PdfDocument {
+    fn page_count(&self) -> Result<i32, ProcessError>;
+    fn render_page(&self, page_num: i32, scale: f32) -> Result<DynamicImage, ProcessError>;
+    fn page_text(&self, page_num: i32) -> Result<String, ProcessError>;
+
+    /// Chapter titles with the zero-based page each one starts on, in page order
+    fn chapters(&self) -> Vec<(i32, String)> {
+        Vec::new()
+    }
+}
+
+/// A PDF rendering backend
+pub trait PdfRenderer: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn open(&self, path: &Path) -> Result<Box<dyn PdfDocument>, ProcessError>;
+}
+
+/// Get the renderer selected in the config
+pub fn renderer_for(kind: &PdfRendererKind) -> Box<dyn PdfRenderer> {
+    match kind {
+        PdfRendererKind::Mupdf => Box::new(MupdfRenderer),
+        PdfRendererKind::Poppler => Box::new(PopplerRenderer),
+    }
+}
+
+pub struct MupdfRenderer;
+
+struct MupdfDocument {
+    doc: MuDocument,
+}
+
+impl PdfRenderer for MupdfRenderer {
+    fn name(&self) -> &'static str {
+        "mupdf"
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn PdfDocument>, ProcessError> {
+        let doc = MuDocument::open(path.to_str().ok_or_else(|| ProcessError::ConversionFailed("Invalid path".to_string()))?)
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+        Ok(Box::new(MupdfDocument { doc }))
+    }
+}
+
+impl PdfDocument for MupdfDocument {
+    fn page_count(&self) -> Result<i32, ProcessError> {
+        self.doc.page_count()
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))
+    }
+
+    // mupdf applies the page /Rotate attribute when bounding and rendering pages
+    fn render_page(&self, page_num: i32, scale: f32) -> Result<DynamicImage, ProcessError> {
+        let page = self.doc.load_page(page_num)
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+        // Create pixmap for rendering
+        let pixmap = page.to_pixmap(
+            &Matrix::new_scale(scale, scale),
+            &Colorspace::device_rgb(),
+            1.0,
+            false
+        ).map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+        // Convert to DynamicImage
+        let samples = pixmap.samples();
+        let width = pixmap.width() as u32;
+        let height = pixmap.height() as u32;
+        let stride = pixmap.stride();
+        let n = pixmap.n();
+
+        // Pre-calculate buffer size and allocate it in one go
+        let buffer_size = (width * height * 3) as usize;
+        let mut rgb_data = vec![0u8; buffer_size];
+
+        // Process all pixels in a single pass
+        let has_alpha = n == 4;
+        let mut i = 0;
+
+        for y in 0..height {
+            let row_start = y as usize * stride as usize;
+            for x in 0..width {
+                let pixel_start = row_start + x as usize * n as usize;
+                if pixel_start + (n as usize) > samples.len() {
+                    rgb_data[i] = 255;
+                    rgb_data[i + 1] = 255;
+                    rgb_data[i + 2] = 255;
+                } else {
+                    let alpha = if has_alpha {
+                        samples[pixel_start + 3] as f32 / 255.0
+                    } else {
+                        1.0
+                    };
+
+                    for j in 0..3 {
+                        let color = samples[pixel_start + j] as f32 * alpha + 255.0 * (1.0 - alpha);
+                        rgb_data[i + j] = color as u8;
+                    }
+                }
+                i += 3;
+            }
+        }
+
+        // Create the image
+        let rgb_image = image::RgbImage::from_raw(width, height, rgb_data)
+            .ok_or_else(|| ProcessError::ConversionFailed("Failed to create image".to_string()))?;
+
+        Ok(DynamicImage::ImageRgb8(rgb_image))
+    }
+
+    fn page_text(&self, page_num: i32) -> Result<String, ProcessError> {
+        let page = self.doc.load_page(page_num)
+            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+        page.bounds()
+            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+        page.to_text()
+            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))
+    }
+
+    fn chapters(&self) -> Vec<(i32, String)> {
+        let mut chapters = Vec::new();
+        if let Ok(outlines) = self.doc.outlines() {
+            flatten_outlines(&outlines, &mut chapters);
+        }
+        chapters.sort_by_key(|(page, _)| *page);
+        chapters
+    }
+}
+
+fn flatten_outlines(outlines: &[Outline], chapters: &mut Vec<(i32, String)>) {
+    for outline in outlines {
+        if let Some(page) = outline.page {
+            let title = outline.title.trim();
+            if !title.is_empty() {
+                chapters.push((page as i32, title.to_string()));
+            }
+        }
+        flatten_outlines(&outline.down, chapters);
+    }
+}
+
+pub struct PopplerRenderer;
+
+struct PopplerDoc {
+    doc: PopplerDocument,
+}
+
+impl PdfRenderer for PopplerRenderer {
+    fn name(&self) -> &'static str {
+        "poppler"
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn PdfDocument>, ProcessError> {
+        let doc = PopplerDocument::new_from_file(path, "")
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+        Ok(Box::new(PopplerDoc { doc }))
+    }
+}
+
+impl PdfDocument for PopplerDoc {
+    fn page_count(&self) -> Result<i32, ProcessError> {
+        Ok(self.doc.get_n_pages() as i32)
+    }
+
+    // Poppler applies the page /Rotate attribute when sizing and rendering pages
+    fn render_page(&self, page_num: i32, scale: f32) -> Result<DynamicImage, ProcessError> {
+        let page = self.doc.get_page(page_num as usize)
+            .ok_or_else(|| ProcessError::ConversionFailed(format!("Page {} not found", page_num + 1)))?;
+
+        let (page_width, page_height) = page.get_size();
+        let width = (page_width * scale as f64).ceil() as i32;
+        let height = (page_height * scale as f64).ceil() as i32;
+
+        let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+        {
+            let context = cairo::Context::new(&surface)
+                .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+            // Paint a white background so the result is fully opaque
+            context.set_source_rgb(1.0, 1.0, 1.0);
+            context.paint()
+                .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+            context.scale(scale as f64, scale as f64);
+            page.render(&context);
+        }
+
+        surface.flush();
+        let stride = surface.stride() as usize;
+        let data = surface.data()
+            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+
+        // Cairo stores ARGB32 as native-endian words, i.e. BGRA bytes on little-endian
+        let (width, height) = (width as u32, height as u32);
+        let mut rgb_data = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height as usize {
+            let row = &data[y * stride..y * stride + width as usize * 4];
+            for pixel in row.chunks_exact(4) {
+                let argb = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                rgb_data.push((argb >> 16) as u8);
+                rgb_data.push((argb >> 8) as u8);
+                rgb_data.push(argb as u8);
+            }
+        }
+
+        let rgb_image = image::RgbImage::from_raw(width, height, rgb_data)
+            .ok_or_else(|| ProcessError::ConversionFailed("Failed to create image".to_string()))?;
+
+        Ok(DynamicImage::ImageRgb8(rgb_image))
+    }
+
+    fn page_text(&self, page_num: i32) -> Result<String, ProcessError> {
+        let page = self.doc.get_page(page_num as usize)
+            .ok_or_else(|| ProcessError::ExtractionFailed(format!("Page {} not found", page_num + 1)))?;
+        Ok(page.get_text().unwrap_or_default().to_string())
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use tracing::debug;
+use zip::ZipArchive;
+use crate::types::ProcessError;
+use super::table::markdown_table;
+use super::package::{attribute, part_relationships, read_part, resolve_target};
+
+/// Most category rows rendered per chart
+const MAX_CHART_ROWS: usize = 100;
+
+/// One data series of a chart, with its cached points by index
+#[derive(Default)]
+struct Series {
+    kind: String,
+    name: Option<String>,
+    categories: BTreeMap<usize, String>,
+    values: BTreeMap<usize, String>,
+}
+
+/// A chart's type, title and cached series data, as saved in its `chart*.xml` part
+#[derive(Default)]
+struct Chart {
+    title: Option<String>,
+    series: Vec<Series>,
+}
+
+/// Each chart a part places, rendered as a data table, keyed by relationship id
+pub(crate) fn load_charts(archive: &mut ZipArchive<File>, part: &str) -> Result<HashMap<String, String>, ProcessError> {
+    let mut charts = HashMap::new();
+    for (id, rel) in part_relationships(archive, part)? {
+        if !rel.kind.ends_with("/chart") {
+            continue;
+        }
+        let target = resolve_target(part, &rel.target);
+        let Some(content) = read_part(archive, &target)? else {
+            debug!("Chart {} not found in package", target);
+            continue;
+        };
+        let text = parse_chart(&content)?.to_markdown();
+        if !text.is_empty() {
+            charts.insert(id, text);
+        }
+    }
+    Ok(charts)
+}
+
+/// The chart type named by a plot element such as `c:barChart`
+fn chart_kind(name: &[u8]) -> Option<String> {
+    let name = std::str::from_utf8(name).ok()?;
+    let kind = name.strip_prefix("c:")?.strip_suffix("Chart")?;
+    (!kind.is_empty()).then(|| kind.to_string())
+}
+
+/// Read a chart part's title and the cached values of each series
+fn parse_chart(content: &str) -> Result<Chart, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+
+    let mut chart = Chart::default();
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut kind = String::new();
+    let mut series: Option<Series> = None;
+    let mut point = 0;
+    let mut title = String::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf);
+        match event {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                let name = e.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"c:ser" => series = Some(Series { kind: kind.clone(), ..Series::default() }),
+                    b"c:pt" => point = attribute(e, b"idx").and_then(|idx| idx.parse().ok()).unwrap_or(0),
+                    // Bar charts are columns unless their bars run sideways
+                    b"c:barDir" if attribute(e, b"val").as_deref() == Some("col") => {
+                        kind = kind.replacen("bar", "column", 1);
+                    }
+                    name => {
+                        if let Some(chart_kind) = chart_kind(name) {
+                            kind = chart_kind;
+                        }
+                    }
+                }
+                if matches!(event, Ok(Event::Start(_))) {
+                    path.push(name);
+                }
+            }
+            Ok(Event::Text(e)) => {
+                let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                let within = |element: &[u8]| path.iter().any(|name| name.as_slice() == element);
+                let leaf = path.last().map(Vec::as_slice);
+                if let Some(series) = series.as_mut() {
+                    if leaf != Some(b"c:v") {
+                        // Only cached values hold series data
+                    } else if within(b"c:tx") {
+                        series.name = Some(text.trim().to_string());
+                    } else if within(b"c:cat") || within(b"c:xVal") {
+                        // Multi-level categories list the innermost level first
+                        series.categories.entry(point).or_insert_with(|| text.trim().to_string());
+                    } else if within(b"c:val") || within(b"c:yVal") {
+                        series.values.insert(point, text.trim().to_string());
+                    }
+                } else if within_title(&path) && matches!(leaf, Some(b"a:t") | Some(b"c:v")) {
+                    title.push_str(&text);
+                }
+            }
+            Ok(Event::End(ref e)) => {
+                match e.name().as_ref() {
+                    b"c:ser" => {
+                        if let Some(series) = series.take() {
+                            chart.series.push(series);
+                        }
+                    }
+                    b"a:p" if within_title(&path) => title.push(' '),
+                    _ => (),
+                }
+                path.pop();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    chart.title = (!title.is_empty()).then_some(title);
+    Ok(chart)
+}
+
+/// Whether the open elements are the chart's own title; axis titles sit inside the plot area
+fn within_title(path: &[Vec<u8>]) -> bool {
+    path.iter().any(|name| name.as_slice() == b"c:title") && !path.iter().any(|name| name.as_slice() == b"c:plotArea")
+}
+
+impl Chart {
+    /// A heading naming the chart and its type, over a table with a row per category and a column per series
+    fn to_markdown(&self) -> String {
+        if self.series.is_empty() {
+            return String::new();
+        }
+
+        let mut kinds: Vec<&str> = Vec::new();
+        for series in &self.series {
+            if !series.kind.is_empty() && !kinds.contains(&series.kind.as_str()) {
+                kinds.push(&series.kind);
+            }
+        }
+        let mut heading = match &self.title {
+            Some(title) => format!("Chart: {}", title),
+            None => "Chart".to_string(),
+        };
+        if !kinds.is_empty() {
+            heading.push_str(&format!(" ({})", kinds.join(", ")));
+        }
+
+        let count = self.series.iter()
+            .flat_map(|series| series.categories.keys().chain(series.values.keys()))
+            .max()
+            .map_or(0, |last| last + 1);
+        let categories = self.series.iter()
+            .map(|series| &series.categories)
+            .find(|categories| !categories.is_empty());
+
+        let mut header = vec!["Category".to_string()];
+        for (index, series) in self.series.iter().enumerate() {
+            header.push(series.name.clone().unwrap_or_else(|| format!("Series {}", index + 1)));
+        }
+        let mut rows = vec![header];
+        for point in 0..count.min(MAX_CHART_ROWS) {
+            let category = categories
+                .and_then(|categories| categories.get(&point).cloned())
+                .unwrap_or_else(|| (point + 1).to_string());
+            let mut row = vec![category];
+            row.extend(self.series.iter().map(|series| series.values.get(&point).cloned().unwrap_or_default()));
+            rows.push(row);
+        }
+
+        let mut text = format!("{}\n{}", heading, markdown_table(&rows));
+        if count > MAX_CHART_ROWS {
+            text.push_str(&format!("({} of {} categories shown)\n", MAX_CHART_ROWS, count));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chart() {
+        let content = r#"<c:chartSpace><c:chart>
+            <c:title><c:tx><c:rich><a:p><a:r><a:t>Revenue by</a:t></a:r><a:r><a:t xml:space="preserve"> region</a:t></a:r></a:p></c:rich></c:tx></c:title>
+            <c:plotArea>
+              <c:barChart><c:barDir val="col"/>
+                <c:ser><c:tx><c:strRef><c:strCache><c:pt idx="0"><c:v>2023</c:v></c:pt></c:strCache></c:strRef></c:tx>
+                  <c:cat><c:strRef><c:strCache><c:pt idx="0"><c:v>EMEA</c:v></c:pt><c:pt idx="1"><c:v>APAC</c:v></c:pt></c:strCache></c:strRef></c:cat>
+                  <c:val><c:numRef><c:numCache><c:pt idx="0"><c:v>10</c:v></c:pt><c:pt idx="1"><c:v>7.5</c:v></c:pt></c:numCache></c:numRef></c:val></c:ser>
+              </c:barChart>
+              <c:lineChart>
+                <c:ser><c:tx><c:v>Target</c:v></c:tx><c:val><c:numRef><c:numCache><c:pt idx="1"><c:v>8</c:v></c:pt></c:numCache></c:numRef></c:val></c:ser>
+              </c:lineChart>
+              <c:valAx><c:title><c:tx><c:rich><a:p><a:r><a:t>EUR m</a:t></a:r></a:p></c:rich></c:tx></c:title></c:valAx>
+            </c:plotArea>
+        </c:chart></c:chartSpace>"#;
+
+        assert_eq!(
+            parse_chart(content).unwrap().to_markdown(),
+            "Chart: Revenue by region (column, line)\n| Category | 2023 | Target |\n| --- | --- | --- |\n| EMEA | 10 |  |\n| APAC | 7.5 | 8 |\n"
+        );
+    }
+}
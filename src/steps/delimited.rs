@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use calamine::DataType;
+use csv::{ByteRecord, ReaderBuilder, StringRecord};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use tracing::debug;
+use crate::types::{Config, ProcessError, RowSampling};
+use super::table::{SheetTable, sample_rows};
+use super::profile::{ColumnProfile, ColumnProfiler};
+
+/// Candidate delimiters, in order of preference when scores tie
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+const SNIFF_ROWS: usize = 50;
+const SNIFF_BYTES: usize = 4096;
+/// Leading bytes read to detect the encoding and delimiter
+const SAMPLE_BYTES: u64 = 64 * 1024;
+
+/// A parsed delimited text file along with what was detected about it
+pub(crate) struct DelimitedText {
+    pub table: SheetTable,
+    pub profile: Option<Vec<ColumnProfile>>,
+    pub delimiter: u8,
+    pub encoding: &'static str,
+}
+
+/// Read a CSV/TSV file into a table, sampling rows and columns down to the configured limits
+///
+/// The file is streamed, so only the sampled rows are held in memory however large it is.
+pub(crate) fn read_delimited(path: &Path, name: &str, config: &Config) -> Result<DelimitedText, ProcessError> {
+    let mut sample = Vec::new();
+    File::open(path)?.take(SAMPLE_BYTES).read_to_end(&mut sample)?;
+    let encoding = detect_encoding(&sample);
+    let (text, _) = encoding.decode_with_bom_removal(&sample);
+    let delimiter = sniff_delimiter(&text);
+    debug!("Detected {} encoding and {:?} delimiter", encoding.name(), delimiter as char);
+
+    // Head sampling knows its rows up front; the others size the file in a first pass
+    let limit = config.max_rows.max(1) as usize;
+    let counted = match config.row_sampling {
+        RowSampling::Head => None,
+        _ => Some(count_records(path, encoding, delimiter)?),
+    };
+    let keep = match counted {
+        Some((total_rows, _)) => sample_rows(total_rows, limit, config.row_sampling),
+        None => (0..limit).collect(),
+    };
+
+    let mut keep = keep.into_iter().peekable();
+    let mut profiler = config.sheet_summary.then(ColumnProfiler::default);
+    let mut rows: Vec<Vec<DataType>> = Vec::new();
+    let mut reader = reader_for(open_decoded(path, encoding)?, delimiter);
+    let mut record = StringRecord::new();
+    let (mut total_rows, mut total_cols) = (0, 0);
+    while reader.read_record(&mut record).map_err(|e| ProcessError::ExtractionFailed(e.to_string()))? {
+        let kept = keep.next_if_eq(&total_rows).is_some();
+        total_rows += 1;
+        total_cols = total_cols.max(record.len());
+        if kept || profiler.is_some() {
+            let row: Vec<DataType> = record.iter().take(config.max_cols.max(1) as usize).map(to_cell).collect();
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.observe(&row);
+            }
+            if kept {
+                rows.push(row);
+            }
+        }
+
+        // Profiling needs every row, and without a first pass the rest is still counted
+        if keep.peek().is_none() && profiler.is_none() && counted.is_some() {
+            break;
+        }
+    }
+
+    let table = SheetTable::new(name, rows, counted.unwrap_or((total_rows, total_cols)));
+    Ok(DelimitedText {
+        profile: profiler.map(|profiler| profiler.finish(&table)),
+        table,
+        delimiter,
+        encoding: encoding.name(),
+    })
+}
+
+/// Rows and the widest row of a file, without keeping any of them
+fn count_records(path: &Path, encoding: &'static Encoding, delimiter: u8) -> Result<(usize, usize), ProcessError> {
+    let mut reader = reader_for(open_decoded(path, encoding)?, delimiter);
+    let mut record = ByteRecord::new();
+    let (mut rows, mut cols) = (0, 0);
+    while reader.read_byte_record(&mut record).map_err(|e| ProcessError::ExtractionFailed(e.to_string()))? {
+        rows += 1;
+        cols = cols.max(record.len());
+    }
+    Ok((rows, cols))
+}
+
+/// The file as a stream of UTF-8, without its byte order mark
+fn open_decoded(path: &Path, encoding: &'static Encoding) -> Result<impl Read, ProcessError> {
+    let file = File::open(path)?;
+    Ok(DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .strip_bom(true)
+        .build(BufReader::new(file)))
+}
+
+/// Guess the encoding from the byte order mark if present, otherwise UTF-16, UTF-8 or Windows-1252
+fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+
+    if let Some(encoding) = sniff_utf16(sample) {
+        return encoding;
+    }
+
+    // A character cut off where the sample ends is still valid UTF-8
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        Err(e) if e.error_len().is_none() && sample.len() as u64 == SAMPLE_BYTES => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+/// Detect BOM-less UTF-16 from the zero high bytes of ASCII characters
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    if odd_zeros * 10 > pairs * 4 && even_zeros * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 > pairs * 4 && odd_zeros * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Pick the delimiter that splits the leading rows into the most consistent number of fields
+fn sniff_delimiter(text: &str) -> u8 {
+    let mut best = (b',', (0, 0));
+    for delimiter in DELIMITERS {
+        let score = delimiter_score(text, delimiter);
+        if score > best.1 {
+            best = (delimiter, score);
+        }
+    }
+    best.0
+}
+
+fn reader_for<R: Read>(source: R, delimiter: u8) -> csv::Reader<R> {
+    ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(source)
+}
+
+fn delimiter_score(text: &str, delimiter: u8) -> (usize, usize) {
+    let counts: Vec<usize> = reader_for(text.as_bytes(), delimiter).records()
+        .take(SNIFF_ROWS)
+        .map_while(Result::ok)
+        .map(|record| record.len())
+        .collect();
+
+    match counts.first() {
+        Some(&fields) if fields > 1 => (counts.iter().filter(|&&count| count == fields).count(), fields),
+        _ => (0, 0),
+    }
+}
+
+fn to_cell(field: &str) -> DataType {
+    if field.is_empty() {
+        DataType::Empty
+    } else {
+        DataType::String(field.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_delimiter() {
+        assert_eq!(sniff_delimiter("a,b,c\n1,2,3\n"), b',');
+        assert_eq!(sniff_delimiter("name;price\n\"x, y\";1,50\nz;2,00\n"), b';');
+        assert_eq!(sniff_delimiter("a\tb\n1\t2\n"), b'\t');
+        assert_eq!(sniff_delimiter("a|b|c\n1|2|3\n"), b'|');
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFa,b"), UTF_8);
+        assert_eq!(detect_encoding(b"a\0,\0b\0"), UTF_16LE);
+        assert_eq!(detect_encoding(b"caf\xE9"), WINDOWS_1252);
+
+        let mut text = String::new();
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(UTF_16LE))
+            .strip_bom(true)
+            .build(&b"\xFF\xFEa\0,\0b\0"[..])
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "a,b");
+    }
+}
@@ -0,0 +1,768 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::QName;
+use quick_xml::reader::Reader;
+use zip::ZipArchive;
+use crate::types::{ProcessError, TrackedChanges};
+use super::chart::load_charts;
+use super::media::{EmbeddedImage, ImageRefs};
+use super::table::{flatten_table, markdown_table, push_line};
+use super::package::{attribute, read_part};
+
+/// Most grid columns a merged cell expands to
+const MAX_SPAN: usize = 64;
+
+/// How the items at one level of a Word list are marked
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ListMarker {
+    Bullet,
+    Number { start: usize },
+    Hidden,
+}
+
+/// The parts of a paragraph style that shape its Markdown
+#[derive(Default)]
+struct ParagraphStyle {
+    based_on: Option<String>,
+    heading: Option<usize>,
+    num_id: Option<String>,
+    level: Option<usize>,
+}
+
+/// Styles and list definitions the document body refers to, and which revisions to read
+#[derive(Default)]
+struct Definitions {
+    styles: HashMap<String, ParagraphStyle>,
+    /// List levels by `w:numId`
+    numbering: HashMap<String, Vec<ListMarker>>,
+    tracked_changes: TrackedChanges,
+}
+
+/// A `w:p` being read, with the properties set directly on it
+#[derive(Default)]
+struct Paragraph {
+    text: String,
+    style: Option<String>,
+    outline: Option<usize>,
+    num_id: Option<String>,
+    level: Option<usize>,
+    /// A page break was seen after the paragraph's text started
+    page_break: bool,
+}
+
+/// A `w:tc` being read, with how far it spans and whether it continues the cell above
+#[derive(Default)]
+struct Cell {
+    text: String,
+    span: usize,
+    merged: bool,
+}
+
+/// An open `w:tbl`, collecting its rows of cells
+#[derive(Default)]
+struct Table {
+    rows: Vec<Vec<Cell>>,
+}
+
+/// Parts of a document that its body refers to by number
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NoteKind {
+    Footnote,
+    Endnote,
+    Comment,
+}
+
+/// Labels for notes, comments and pictures, numbered in the order they are first referred to
+#[derive(Default)]
+struct References {
+    numbers: HashMap<(NoteKind, String), usize>,
+    counts: HashMap<NoteKind, usize>,
+    /// The part being read, whose relationships resolve picture and chart ids
+    part: String,
+    images: ImageRefs,
+    /// Charts of the part being read as data tables, by relationship id
+    charts: HashMap<String, String>,
+}
+
+impl References {
+    fn number(&mut self, kind: NoteKind, id: &str) -> usize {
+        if let Some(number) = self.numbers.get(&(kind, id.to_string())) {
+            return *number;
+        }
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        self.numbers.insert((kind, id.to_string()), *count);
+        *count
+    }
+
+    fn label(&mut self, kind: NoteKind, id: &str) -> String {
+        let prefix = match kind {
+            NoteKind::Footnote => "",
+            NoteKind::Endnote => "E",
+            NoteKind::Comment => "C",
+        };
+        format!("[{}{}]", prefix, self.number(kind, id))
+    }
+}
+
+/// A Word document's body pages, with its headers, footers, notes and comments as named sections
+pub(crate) struct DocxText {
+    pub(crate) pages: Vec<String>,
+    pub(crate) sections: Vec<(&'static str, String)>,
+    /// Tracked insertions and deletions seen, whichever way they were read
+    pub(crate) revisions: usize,
+    pub(crate) images: Vec<EmbeddedImage>,
+}
+
+/// Text of a Word document as Markdown, one body entry per page as last laid out by Word
+pub(crate) fn read_docx(path: &Path, tracked_changes: TrackedChanges) -> Result<Option<DocxText>, ProcessError> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+    let Some(document) = read_part(&mut archive, "word/document.xml")? else {
+        return Ok(None);
+    };
+
+    let mut definitions = Definitions { tracked_changes, ..Definitions::default() };
+    if let Some(styles) = read_part(&mut archive, "word/styles.xml")? {
+        definitions.styles = parse_styles(&styles)?;
+    }
+    if let Some(numbering) = read_part(&mut archive, "word/numbering.xml")? {
+        definitions.numbering = parse_numbering(&numbering)?;
+    }
+
+    let mut references = References {
+        part: "word/document.xml".to_string(),
+        charts: load_charts(&mut archive, "word/document.xml")?,
+        ..References::default()
+    };
+    let revisions = count_revisions(&document)?;
+    let pages = parse_document(&document, &definitions, &mut references)?;
+
+    let mut sections = Vec::new();
+    for (section, prefix) in [("headers", "word/header"), ("footers", "word/footer")] {
+        // First page, even page and default variants often repeat each other
+        let mut texts: Vec<String> = Vec::new();
+        for name in part_names(&archive, prefix) {
+            if let Some(content) = read_part(&mut archive, &name)? {
+                references.charts = load_charts(&mut archive, &name)?;
+                references.part = name;
+                let text = parse_document(&content, &definitions, &mut references)?.join("\n");
+                if !text.trim().is_empty() && !texts.contains(&text) {
+                    texts.push(text);
+                }
+            }
+        }
+        if !texts.is_empty() {
+            sections.push((section, texts.join("\n")));
+        }
+    }
+
+    let notes = [
+        ("footnotes", "word/footnotes.xml", "w:footnote", NoteKind::Footnote),
+        ("endnotes", "word/endnotes.xml", "w:endnote", NoteKind::Endnote),
+        ("comments", "word/comments.xml", "w:comment", NoteKind::Comment),
+    ];
+    for (section, name, element, kind) in notes {
+        if let Some(content) = read_part(&mut archive, name)? {
+            references.charts = load_charts(&mut archive, name)?;
+            references.part = name.to_string();
+            let text = parse_notes(&content, element.as_bytes(), kind, &definitions, &mut references)?;
+            if !text.is_empty() {
+                sections.push((section, text));
+            }
+        }
+    }
+
+    let images = std::mem::take(&mut references.images).load(&mut archive)?;
+    Ok(Some(DocxText { pages, sections, revisions, images }))
+}
+
+/// Number of tracked insertions and deletions in a story
+fn count_revisions(content: &str) -> Result<usize, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut count = 0;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if revision(e.name().as_ref()).is_some() => count += 1,
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+    }
+    Ok(count)
+}
+
+/// Whether an element wraps inserted (`true`) or deleted (`false`) runs
+fn revision(name: &[u8]) -> Option<bool> {
+    match name {
+        b"w:ins" | b"w:moveTo" => Some(true),
+        b"w:del" | b"w:moveFrom" => Some(false),
+        _ => None,
+    }
+}
+
+/// Names of numbered parts such as `word/header1.xml`, in number order
+fn part_names(archive: &ZipArchive<File>, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = archive.file_names()
+        .filter(|name| {
+            name.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(".xml"))
+                .is_some_and(|number| number.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+        .collect();
+    names.sort_by_key(|name| (name.len(), name.clone()));
+    names
+}
+
+fn number_attribute(element: &BytesStart) -> Option<usize> {
+    attribute(element, b"w:val").and_then(|value| value.parse().ok())
+}
+
+/// Heading levels and list numbering of paragraph styles, by style id
+fn parse_styles(content: &str) -> Result<HashMap<String, ParagraphStyle>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut styles = HashMap::new();
+    let mut current: Option<(String, ParagraphStyle)> = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"w:style" => {
+                current = attribute(e, b"w:styleId").map(|id| (id, ParagraphStyle::default()));
+            }
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                let Some((_, style)) = current.as_mut() else {
+                    buf.clear();
+                    continue;
+                };
+                match e.name().as_ref() {
+                    b"w:name" => {
+                        let name = attribute(e, b"w:val").unwrap_or_default().to_lowercase();
+                        if name == "title" {
+                            style.heading = Some(1);
+                        } else if let Some(level) = name.strip_prefix("heading ").and_then(|l| l.parse().ok()) {
+                            style.heading = Some(level);
+                        }
+                    }
+                    b"w:basedOn" => style.based_on = attribute(e, b"w:val"),
+                    // Outline levels count from zero, and 9 is body text
+                    b"w:outlineLvl" => {
+                        if let Some(level) = number_attribute(e).filter(|level| *level < 9) {
+                            style.heading = style.heading.or(Some(level + 1));
+                        }
+                    }
+                    b"w:numId" => style.num_id = attribute(e, b"w:val"),
+                    b"w:ilvl" => style.level = number_attribute(e),
+                    _ => (),
+                }
+            }
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"w:style" => {
+                if let Some((id, style)) = current.take() {
+                    styles.insert(id, style);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(styles)
+}
+
+/// List levels of each `w:num`, resolved through its abstract definition
+fn parse_numbering(content: &str) -> Result<HashMap<String, Vec<ListMarker>>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut abstracts: HashMap<String, Vec<ListMarker>> = HashMap::new();
+    let mut nums: Vec<(String, String)> = Vec::new();
+    let mut abstract_id: Option<String> = None;
+    let mut level: Option<(usize, ListMarker)> = None;
+    let mut num_id: Option<String> = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                b"w:abstractNum" => abstract_id = attribute(e, b"w:abstractNumId"),
+                b"w:lvl" if abstract_id.is_some() => {
+                    level = attribute(e, b"w:ilvl")
+                        .and_then(|l| l.parse().ok())
+                        .map(|l| (l, ListMarker::Number { start: 1 }));
+                }
+                b"w:start" => {
+                    if let Some((_, ListMarker::Number { start })) = level.as_mut() {
+                        *start = number_attribute(e).unwrap_or(1);
+                    }
+                }
+                b"w:numFmt" => {
+                    if let Some((_, marker)) = level.as_mut() {
+                        match attribute(e, b"w:val").as_deref() {
+                            Some("bullet") => *marker = ListMarker::Bullet,
+                            Some("none") => *marker = ListMarker::Hidden,
+                            _ => (),
+                        }
+                    }
+                }
+                b"w:num" => num_id = attribute(e, b"w:numId"),
+                b"w:abstractNumId" => {
+                    if let (Some(num), Some(id)) = (num_id.as_ref(), attribute(e, b"w:val")) {
+                        nums.push((num.clone(), id));
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::End(ref e)) => match e.name().as_ref() {
+                b"w:lvl" => {
+                    if let (Some(id), Some((index, marker))) = (abstract_id.as_ref(), level.take()) {
+                        let levels = abstracts.entry(id.clone()).or_default();
+                        if levels.len() <= index {
+                            levels.resize(index + 1, ListMarker::Bullet);
+                        }
+                        levels[index] = marker;
+                    }
+                }
+                b"w:abstractNum" => abstract_id = None,
+                b"w:num" => num_id = None,
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(nums.into_iter()
+        .filter_map(|(num, id)| abstracts.get(&id).map(|levels| (num, levels.clone())))
+        .collect())
+}
+
+/// Each note or comment of a part on its own line after its label, in label order
+fn parse_notes(
+    content: &str,
+    element: &[u8],
+    kind: NoteKind,
+    definitions: &Definitions,
+    references: &mut References,
+) -> Result<String, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut notes: Vec<(usize, String)> = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == element => {
+                let id = attribute(&e, b"w:id");
+                // Separators between the body and its notes have a type
+                let separator = attribute(&e, b"w:type").is_some_and(|kind| kind != "normal");
+                let author = attribute(&e, b"w:author");
+                let inner = reader.read_text(QName(element))
+                    .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                let Some(id) = id.filter(|_| !separator) else {
+                    continue;
+                };
+
+                let text = parse_document(&inner, definitions, references)?.join("\n");
+                let text: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+                if text.is_empty() {
+                    continue;
+                }
+                let label = references.label(kind, &id);
+                let line = match author {
+                    Some(author) => format!("{} {}: {}", label, author, text.join(" ")),
+                    None => format!("{} {}", label, text.join(" ")),
+                };
+                notes.push((references.number(kind, &id), line));
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+    }
+    notes.sort_by_key(|(number, _)| *number);
+    Ok(notes.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join("\n"))
+}
+
+/// Walk the paragraphs of `document.xml` or another story, keeping headings, lists and tables as Markdown
+fn parse_document(content: &str, definitions: &Definitions, references: &mut References) -> Result<Vec<String>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut skip_buf = Vec::new();
+
+    let mut pages: Vec<String> = Vec::new();
+    let mut blocks: Vec<String> = Vec::new();
+    // Paragraphs nest when a text box sits inside one
+    let mut paragraphs: Vec<Paragraph> = Vec::new();
+    let mut tables: Vec<Table> = Vec::new();
+    let mut counters: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut in_properties = false;
+    let mut in_text = false;
+    let mut image_description: Option<String> = None;
+
+    loop {
+        let event = reader.read_event_into(&mut buf);
+        let empty = matches!(event, Ok(Event::Empty(_)));
+        match event {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                // Fallback content repeats its choice, and property changes hold superseded properties
+                name @ (b"mc:Fallback" | b"w:pPrChange" | b"w:tblPrChange" | b"w:trPrChange" | b"w:tcPrChange") if !empty => {
+                    reader.read_to_end_into(QName(name), &mut skip_buf)
+                        .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                }
+                name if !empty && revision(name).is_some() => {
+                    let inserted = revision(name) == Some(true);
+                    let skip = match definitions.tracked_changes {
+                        TrackedChanges::Accept => !inserted,
+                        TrackedChanges::Reject => inserted,
+                        TrackedChanges::Markup => false,
+                    };
+                    if skip {
+                        reader.read_to_end_into(QName(name), &mut skip_buf)
+                            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                    } else if definitions.tracked_changes == TrackedChanges::Markup {
+                        let tag = if inserted { "ins" } else { "del" };
+                        let author = attribute(e, b"w:author").unwrap_or_default().replace('"', "'");
+                        push_text(&mut paragraphs, &format!("<{} author=\"{}\">", tag, author));
+                    }
+                }
+                b"w:p" if !empty => paragraphs.push(Paragraph::default()),
+                b"w:pPr" if !empty => in_properties = true,
+                // Deleted runs hold their text in `w:delText`, read whenever deletions are kept
+                b"w:t" | b"w:delText" if !empty => in_text = true,
+                b"w:tbl" if !empty => tables.push(Table::default()),
+                b"w:tr" if !empty => {
+                    if let Some(table) = tables.last_mut() {
+                        table.rows.push(Vec::new());
+                    }
+                }
+                b"w:tc" if !empty => {
+                    if let Some(row) = tables.last_mut().and_then(|table| table.rows.last_mut()) {
+                        row.push(Cell { span: 1, ..Cell::default() });
+                    }
+                }
+                // Grid columns skipped before a row's first cell
+                b"w:gridBefore" => {
+                    if let Some(row) = tables.last_mut().and_then(|table| table.rows.last_mut()) {
+                        row.push(Cell { span: number_attribute(e).unwrap_or(0).min(MAX_SPAN), ..Cell::default() });
+                    }
+                }
+                b"w:gridSpan" => {
+                    if let Some(cell) = current_cell(&mut tables) {
+                        cell.span = number_attribute(e).unwrap_or(1).clamp(1, MAX_SPAN);
+                    }
+                }
+                // A vertical merge without a value continues the cell above
+                b"w:vMerge" => {
+                    if let Some(cell) = current_cell(&mut tables) {
+                        cell.merged = attribute(e, b"w:val").is_none_or(|value| value == "continue");
+                    }
+                }
+                _ if in_properties => {
+                    let Some(paragraph) = paragraphs.last_mut() else {
+                        buf.clear();
+                        continue;
+                    };
+                    match e.name().as_ref() {
+                        b"w:pStyle" => paragraph.style = attribute(e, b"w:val"),
+                        b"w:outlineLvl" => paragraph.outline = number_attribute(e),
+                        b"w:numId" => paragraph.num_id = attribute(e, b"w:val"),
+                        b"w:ilvl" => paragraph.level = number_attribute(e),
+                        _ => (),
+                    }
+                }
+                name @ (b"w:footnoteReference" | b"w:endnoteReference" | b"w:commentReference") => {
+                    let kind = match name {
+                        b"w:footnoteReference" => NoteKind::Footnote,
+                        b"w:endnoteReference" => NoteKind::Endnote,
+                        _ => NoteKind::Comment,
+                    };
+                    if let Some(id) = attribute(e, b"w:id") {
+                        let label = references.label(kind, &id);
+                        push_text(&mut paragraphs, &label);
+                    }
+                }
+                b"w:drawing" | b"w:pict" => image_description = None,
+                // Alt text of a drawing, or the title of an older VML picture
+                b"wp:docPr" | b"pic:cNvPr" | b"v:imagedata" => {
+                    let description = attribute(e, b"descr").or_else(|| attribute(e, b"o:title"));
+                    if let Some(description) = description.filter(|d| !d.trim().is_empty()) {
+                        image_description = Some(description.split_whitespace().collect::<Vec<_>>().join(" "));
+                    }
+                    if let Some(id) = attribute(e, b"r:id").filter(|_| e.name().as_ref() == b"v:imagedata") {
+                        let label = references.images.add(&references.part, &id, pages.len() + 1, image_description.take());
+                        push_text(&mut paragraphs, &label);
+                    }
+                }
+                b"a:blip" => {
+                    if let Some(id) = attribute(e, b"r:embed") {
+                        let label = references.images.add(&references.part, &id, pages.len() + 1, image_description.take());
+                        push_text(&mut paragraphs, &label);
+                    }
+                }
+                b"c:chart" => {
+                    if let Some(chart) = attribute(e, b"r:id").and_then(|id| references.charts.get(&id)) {
+                        push_text(&mut paragraphs, &format!("\n{}", chart));
+                    }
+                }
+                b"w:tab" => push_text(&mut paragraphs, "\t"),
+                b"w:noBreakHyphen" => push_text(&mut paragraphs, "-"),
+                b"w:cr" => push_text(&mut paragraphs, "\n"),
+                b"w:br" if is_page_break(e) => page_break(&mut paragraphs, &mut blocks, &mut pages),
+                b"w:br" => push_text(&mut paragraphs, "\n"),
+                b"w:lastRenderedPageBreak" => page_break(&mut paragraphs, &mut blocks, &mut pages),
+                _ => (),
+            },
+            Ok(Event::Text(e)) if in_text => {
+                let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                push_text(&mut paragraphs, &text);
+            }
+            Ok(Event::End(ref e)) => match e.name().as_ref() {
+                b"w:pPr" => in_properties = false,
+                b"w:t" | b"w:delText" => in_text = false,
+                name if definitions.tracked_changes == TrackedChanges::Markup && revision(name).is_some() => {
+                    let tag = if revision(name) == Some(true) { "ins" } else { "del" };
+                    push_text(&mut paragraphs, &format!("</{}>", tag));
+                }
+                b"w:p" => {
+                    let Some(paragraph) = paragraphs.pop() else {
+                        continue;
+                    };
+                    let text = paragraph.text.trim_end();
+                    if text.trim().is_empty() {
+                        // Nothing to keep
+                    } else if let Some(cell) = current_cell(&mut tables) {
+                        push_line(&mut cell.text, text.trim());
+                    } else {
+                        let prefix = paragraph_prefix(&paragraph, definitions, &mut counters);
+                        blocks.push(format!("{}{}", prefix, text));
+                    }
+                    if paragraph.page_break {
+                        pages.push(std::mem::take(&mut blocks).join("\n"));
+                    }
+                }
+                b"w:tbl" => {
+                    let Some(table) = tables.pop() else {
+                        continue;
+                    };
+                    let rows = table_rows(table);
+                    match current_cell(&mut tables) {
+                        Some(cell) => push_line(&mut cell.text, &flatten_table(&rows)),
+                        None if !rows.is_empty() => blocks.push(markdown_table(&rows)),
+                        None => (),
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    pages.push(blocks.join("\n"));
+    Ok(pages)
+}
+
+fn push_text(paragraphs: &mut [Paragraph], text: &str) {
+    if let Some(paragraph) = paragraphs.last_mut() {
+        paragraph.text.push_str(text);
+    }
+}
+
+fn current_cell(tables: &mut [Table]) -> Option<&mut Cell> {
+    tables.last_mut()
+        .and_then(|table| table.rows.last_mut())
+        .and_then(|row| row.last_mut())
+}
+
+/// Lay a table's cells out on its grid, repeating merged cells into every column and row they cover
+fn table_rows(table: Table) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for row in table.rows {
+        let mut cells = Vec::new();
+        for cell in row {
+            let text = match rows.last() {
+                Some(above) if cell.merged => above.get(cells.len()).cloned().unwrap_or_default(),
+                _ => cell.text,
+            };
+            cells.extend(std::iter::repeat_n(text, cell.span));
+        }
+        rows.push(cells);
+    }
+    // Rows with nothing in them are layout spacing
+    rows.retain(|row| row.iter().any(|cell| !cell.is_empty()));
+    rows
+}
+
+/// End the page before the current paragraph, or after it once the paragraph has text
+fn page_break(paragraphs: &mut [Paragraph], blocks: &mut Vec<String>, pages: &mut Vec<String>) {
+    match paragraphs.last_mut() {
+        Some(paragraph) if !paragraph.text.trim().is_empty() => paragraph.page_break = true,
+        _ => pages.push(std::mem::take(blocks).join("\n")),
+    }
+}
+
+/// The heading or list marker a paragraph starts with, counting list items as they appear
+fn paragraph_prefix(paragraph: &Paragraph, definitions: &Definitions, counters: &mut HashMap<String, Vec<usize>>) -> String {
+    let mut heading = paragraph.outline.filter(|level| *level < 9).map(|level| level + 1);
+    let mut num_id = paragraph.num_id.clone();
+    let mut level = paragraph.level;
+
+    // Properties not set on the paragraph come from its style and the styles it is based on
+    let mut style_id = paragraph.style.clone();
+    for _ in 0..10 {
+        let Some(style) = style_id.as_ref().and_then(|id| definitions.styles.get(id)) else {
+            break;
+        };
+        heading = heading.or(style.heading);
+        if num_id.is_none() {
+            num_id = style.num_id.clone();
+            level = level.or(style.level);
+        }
+        style_id = style.based_on.clone();
+    }
+
+    let mut prefix = String::new();
+    if let Some(heading) = heading {
+        prefix = format!("{} ", "#".repeat(heading.clamp(1, 6)));
+    }
+
+    let level = level.unwrap_or(0);
+    let marker = num_id.as_ref()
+        .and_then(|id| definitions.numbering.get(id))
+        .and_then(|levels| levels.get(level).copied());
+    let (Some(num_id), Some(marker)) = (num_id, marker) else {
+        return prefix;
+    };
+
+    let counts = counters.entry(num_id).or_default();
+    counts.resize(level + 1, 0);
+    counts[level] += 1;
+    match marker {
+        // Numbered headings keep their number but not a list indent
+        ListMarker::Number { start } if heading.is_some() => format!("{}{}. ", prefix, start + counts[level] - 1),
+        _ if heading.is_some() => prefix,
+        ListMarker::Number { start } => format!("{}{}. ", "  ".repeat(level), start + counts[level] - 1),
+        ListMarker::Bullet => format!("{}- ", "  ".repeat(level)),
+        ListMarker::Hidden => "  ".repeat(level),
+    }
+}
+
+/// Check whether a `w:br` element is a page break
+fn is_page_break(element: &BytesStart) -> bool {
+    attribute(element, b"w:type").as_deref() == Some("page")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let styles = r#"<w:styles>
+            <w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/></w:style>
+            <w:style w:type="paragraph" w:styleId="ListBullet"><w:name w:val="List Bullet"/><w:pPr><w:numPr><w:numId w:val="2"/></w:numPr></w:pPr></w:style>
+        </w:styles>"#;
+        let numbering = r#"<w:numbering>
+            <w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:start w:val="1"/><w:numFmt w:val="decimal"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+            <w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+            <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+            <w:num w:numId="2"><w:abstractNumId w:val="1"/></w:num>
+        </w:numbering>"#;
+        let document = r#"<w:document><w:body>
+            <w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Scope</w:t></w:r></w:p>
+            <w:p><w:r><w:t>Hel</w:t></w:r><w:r><w:t xml:space="preserve">lo world</w:t><w:tab/><w:t>x</w:t><w:br/><w:t>y</w:t></w:r></w:p>
+            <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>First</w:t></w:r></w:p>
+            <w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Nested</w:t></w:r></w:p>
+            <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Second</w:t></w:r></w:p>
+            <w:p><w:pPr><w:pStyle w:val="ListBullet"/></w:pPr><w:r><w:t>Point</w:t></w:r></w:p>
+            <w:p><w:r><w:br w:type="page"/></w:r></w:p>
+            <w:p><w:r><w:t>Next page</w:t></w:r></w:p>
+        </w:body></w:document>"#;
+        let definitions = Definitions {
+            styles: parse_styles(styles).unwrap(),
+            numbering: parse_numbering(numbering).unwrap(),
+            ..Definitions::default()
+        };
+
+        assert_eq!(parse_document(document, &definitions, &mut References::default()).unwrap(), vec![
+            "## Scope\nHello world\tx\ny\n1. First\n  - Nested\n2. Second\n- Point",
+            "Next page",
+        ]);
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let document = r#"<w:document><w:body>
+            <w:tbl>
+              <w:tr><w:tc><w:p><w:r><w:t>Item</w:t></w:r></w:p></w:tc><w:tc><w:tcPr><w:gridSpan w:val="2"/></w:tcPr><w:p><w:r><w:t>Terms</w:t></w:r></w:p></w:tc></w:tr>
+              <w:tr><w:tc><w:tcPr><w:vMerge w:val="restart"/></w:tcPr><w:p><w:r><w:t>Fee</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>10</w:t></w:r></w:p><w:p><w:r><w:t>EUR</w:t></w:r></w:p></w:tc>
+                <w:tc><w:tbl><w:tr><w:tc><w:p><w:r><w:t>a</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>b</w:t></w:r></w:p></w:tc></w:tr></w:tbl></w:tc></w:tr>
+              <w:tr><w:tc><w:tcPr><w:vMerge/></w:tcPr><w:p/></w:tc><w:tc><w:p><w:r><w:t>x|y</w:t></w:r></w:p></w:tc><w:tc><w:p/></w:tc></w:tr>
+            </w:tbl>
+            <w:p><w:r><w:t>After</w:t></w:r></w:p>
+        </w:body></w:document>"#;
+
+        assert_eq!(parse_document(document, &Definitions::default(), &mut References::default()).unwrap(), vec![
+            "| Item | Terms | Terms |\n| --- | --- | --- |\n| Fee | 10 EUR | a \\| b |\n| Fee | x\\|y |  |\n\nAfter",
+        ]);
+    }
+
+    #[test]
+    fn test_grid_before_clamped() {
+        let document = r#"<w:document><w:body><w:tbl>
+            <w:tr><w:trPr><w:gridBefore w:val="4000000000"/></w:trPr><w:tc><w:p><w:r><w:t>x</w:t></w:r></w:p></w:tc></w:tr>
+        </w:tbl></w:body></w:document>"#;
+
+        let pages = parse_document(document, &Definitions::default(), &mut References::default()).unwrap();
+        let header = pages[0].lines().next().unwrap();
+        assert!(header.ends_with("|  | x |"));
+        assert_eq!(header.matches(" |").count(), MAX_SPAN + 1);
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        let document = r#"<w:document><w:body><w:p>
+            <w:r><w:t>Terms</w:t></w:r><w:r><w:footnoteReference w:id="5"/></w:r>
+            <w:r><w:t xml:space="preserve"> apply</w:t></w:r><w:r><w:commentReference w:id="0"/></w:r><w:r><w:footnoteReference w:id="2"/></w:r>
+        </w:p></w:body></w:document>"#;
+        let footnotes = r#"<w:footnotes>
+            <w:footnote w:type="separator" w:id="-1"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>
+            <w:footnote w:id="2"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> Second.</w:t></w:r></w:p></w:footnote>
+            <w:footnote w:id="5"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t>First</w:t></w:r></w:p><w:p><w:r><w:t>note.</w:t></w:r></w:p></w:footnote>
+        </w:footnotes>"#;
+        let comments = r#"<w:comments><w:comment w:id="0" w:author="Ann"><w:p><w:r><w:t>Check this.</w:t></w:r></w:p></w:comment></w:comments>"#;
+
+        let definitions = Definitions::default();
+        let mut references = References::default();
+        assert_eq!(parse_document(document, &definitions, &mut references).unwrap(), vec!["Terms[1] apply[C1][2]"]);
+        assert_eq!(
+            parse_notes(footnotes, b"w:footnote", NoteKind::Footnote, &definitions, &mut references).unwrap(),
+            "[1] First note.\n[2] Second."
+        );
+        assert_eq!(
+            parse_notes(comments, b"w:comment", NoteKind::Comment, &definitions, &mut references).unwrap(),
+            "[C1] Ann: Check this."
+        );
+    }
+
+    #[test]
+    fn test_tracked_changes() {
+        let document = r#"<w:document><w:body><w:p>
+            <w:r><w:t xml:space="preserve">Pay within </w:t></w:r>
+            <w:del w:id="1" w:author="Ann"><w:r><w:delText>30</w:delText></w:r></w:del>
+            <w:ins w:id="2" w:author="Bob"><w:r><w:t>60</w:t></w:r></w:ins>
+            <w:r><w:t xml:space="preserve"> days</w:t></w:r>
+        </w:p></w:body></w:document>"#;
+        let read = |tracked_changes| {
+            let definitions = Definitions { tracked_changes, ..Definitions::default() };
+            parse_document(document, &definitions, &mut References::default()).unwrap()
+        };
+
+        assert_eq!(count_revisions(document).unwrap(), 2);
+        assert_eq!(read(TrackedChanges::Accept), vec!["Pay within 60 days"]);
+        assert_eq!(read(TrackedChanges::Reject), vec!["Pay within 30 days"]);
+        assert_eq!(read(TrackedChanges::Markup), vec![r#"Pay within <del author="Ann">30</del><ins author="Bob">60</ins> days"#]);
+    }
+}
@@ -1,7 +1,8 @@
 use std::path::Path;
 use async_trait::async_trait;
+use image::DynamicImage;
 use leptess::LepTess;
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 use tracing::{debug, trace};
 use crate::processor::{ProcessingStep, AsyncProcessor, format_ocr_text, optimize_image, is_meaningful_text};
 use crate::proto::processor::{Query, Attachment};
@@ -9,6 +10,37 @@ use crate::types::{Strategy, ProcessError, Config};
 
 pub struct ImageProcessor;
 
+/// Tesseract reading optimized images through temp files, one engine for all the images of a document
+pub(crate) struct ImageOcr {
+    tesseract: LepTess,
+    temp_dir: TempDir,
+}
+
+impl ImageOcr {
+    pub(crate) fn new(config: &Config) -> Result<Self, ProcessError> {
+        let tesseract = LepTess::new(None, &config.ocr_language)
+            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
+        let temp_dir = tempdir().map_err(ProcessError::IOError)?;
+        Ok(Self { tesseract, temp_dir })
+    }
+
+    /// Text Tesseract finds in an image, saved for it as `file_name`; with `keep_temps` the file is kept in the configured temp directory
+    pub(crate) fn recognize(&mut self, img: &DynamicImage, file_name: &str, config: &Config) -> Result<String, ProcessError> {
+        let dir = if config.keep_temps { config.temp_dir.as_path() } else { self.temp_dir.path() };
+        let temp_path = dir.join(file_name);
+        img.save(&temp_path)
+            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
+
+        self.tesseract.set_image(&temp_path)
+            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
+        let text = self.tesseract.get_utf8_text()
+            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
+
+        trace!("OCR text extracted from {}: {}", file_name, text);
+        Ok(text)
+    }
+}
+
 #[async_trait]
 impl AsyncProcessor for ImageProcessor {
     async fn process(&self, query: &mut Query, config: &Config) -> Result<(), ProcessError> {
@@ -25,29 +57,8 @@ impl AsyncProcessor for ImageProcessor {
             data: buffer,
         });
 
-        // Create temp dir for OCR
-        let temp_dir = tempdir().map_err(|e| ProcessError::IOError(e))?;
-
         // Perform OCR
-        let mut lt = LepTess::new(None, &config.ocr_language)
-            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
-
-        // Save optimized image to temp file for OCR
-        let temp_path = temp_dir.path().join("temp_ocr.png");
-        optimized.save(&temp_path)
-            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
-
-        // Set image for OCR
-        lt.set_image(&temp_path)
-            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
-
-        // Get OCR text
-        let text = lt.get_utf8_text()
-            .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
-
-        trace!("OCR text extracted: {}", text);
-        trace!("Text length: {}", text.trim().len());
-        trace!("Word count: {}", text.trim().split_whitespace().count());
+        let text = ImageOcr::new(config)?.recognize(&optimized, "temp_ocr.png", config)?;
 
         // Only add meaningful text
         if is_meaningful_text(&text, config.ocr_quality_threshold) {
@@ -57,15 +68,6 @@ impl AsyncProcessor for ImageProcessor {
             debug!("Text not meaningful enough");
         }
 
-        // Temp dir will be automatically cleaned up when it goes out of scope
-        // unless keep_temps is true
-        if config.keep_temps {
-            // If we want to keep temps, move the file to the configured temp directory
-            let kept_path = config.temp_dir.join("temp_ocr.png");
-            std::fs::rename(temp_path, kept_path)
-                .map_err(|e| ProcessError::OCRFailed(e.to_string()))?;
-        }
-
         Ok(())
     }
 }
@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use tracing::{debug, warn};
+use zip::ZipArchive;
+use crate::types::ProcessError;
+use super::package::{part_relationships, resolve_target};
+
+/// Most embedded images read from one document
+const MAX_EMBEDDED_IMAGES: usize = 50;
+
+/// A picture placed in a story, by the relationship id of the part that places it
+pub(crate) struct ImageRef {
+    pub number: usize,
+    pub part: String,
+    pub rel_id: String,
+    pub page: usize,
+    pub description: Option<String>,
+}
+
+/// An embedded image read from the package, with the page or slide it first appears on
+pub(crate) struct EmbeddedImage {
+    pub number: usize,
+    pub page: usize,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Pictures seen while reading a package, numbered in the order they first appear
+#[derive(Default)]
+pub(crate) struct ImageRefs {
+    refs: Vec<ImageRef>,
+}
+
+impl ImageRefs {
+    /// Register a picture and return the placeholder that marks its position in the text
+    pub fn add(&mut self, part: &str, rel_id: &str, page: usize, description: Option<String>) -> String {
+        let number = match self.refs.iter().find(|image| image.part == part && image.rel_id == rel_id) {
+            Some(image) => image.number,
+            None => {
+                let number = self.refs.len() + 1;
+                self.refs.push(ImageRef {
+                    number,
+                    part: part.to_string(),
+                    rel_id: rel_id.to_string(),
+                    page,
+                    description: description.clone(),
+                });
+                number
+            }
+        };
+        match description {
+            Some(description) => format!("[Image {}: {}]", number, description),
+            None => format!("[Image {}]", number),
+        }
+    }
+
+    /// Read each distinct image from the package, skipping links to external files
+    pub fn load(self, archive: &mut ZipArchive<File>) -> Result<Vec<EmbeddedImage>, ProcessError> {
+        let mut relationships = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut images = Vec::new();
+        for image in self.refs {
+            if !relationships.contains_key(&image.part) {
+                let rels = part_relationships(archive, &image.part)?;
+                relationships.insert(image.part.clone(), rels);
+            }
+            let Some(rel) = relationships.get(&image.part).and_then(|rels| rels.get(&image.rel_id)) else {
+                continue;
+            };
+            // The same picture is often repeated, such as a logo on every page
+            let target = resolve_target(&image.part, &rel.target);
+            if !seen.insert(target.clone()) {
+                continue;
+            }
+            if images.len() == MAX_EMBEDDED_IMAGES {
+                warn!("Only reading the first {} embedded images", MAX_EMBEDDED_IMAGES);
+                break;
+            }
+
+            let mut data = Vec::new();
+            match archive.by_name(&target) {
+                Ok(mut part) => {
+                    part.read_to_end(&mut data)?;
+                }
+                Err(_) => {
+                    debug!("Embedded image {} not found in package", target);
+                    continue;
+                }
+            }
+            images.push(EmbeddedImage {
+                number: image.number,
+                page: image.page,
+                description: image.description,
+                data,
+            });
+        }
+        Ok(images)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_refs() {
+        let mut refs = ImageRefs::default();
+        assert_eq!(refs.add("word/document.xml", "rId4", 1, Some("Signature".to_string())), "[Image 1: Signature]");
+        assert_eq!(refs.add("word/document.xml", "rId5", 2, None), "[Image 2]");
+        assert_eq!(refs.add("word/document.xml", "rId4", 3, Some("Signature".to_string())), "[Image 1: Signature]");
+        assert_eq!(refs.add("word/header1.xml", "rId4", 1, None), "[Image 3]");
+        assert_eq!(refs.refs.len(), 3);
+    }
+}
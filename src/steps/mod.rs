@@ -1,7 +1,19 @@
 mod text;
 mod spreadsheet;
+mod delimited;
+mod table;
+mod profile;
+mod package;
+mod xlsx;
 mod pdf;
 mod office;
+mod docx;
+mod media;
+mod pptx;
+mod chart;
+mod ole;
+mod opendocument;
+mod rtf;
 mod image;
 
 pub use text::TextProcessor;
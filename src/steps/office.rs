@@ -1,25 +1,50 @@
 use async_trait::async_trait;
 use anyhow::Result;
-use std::path::Path;
-use std::io::{Read, BufReader};
-use quick_xml::events::Event;
-use quick_xml::reader::Reader;
-use zip::ZipArchive;
+use std::path::{Path, PathBuf};
+use std::io::Read;
+use tracing::{debug, warn};
 use crate::types::{ProcessError, Strategy, Config};
-use crate::processor::{ProcessingStep, AsyncProcessor, format_extracted_data, clean_text};
-use crate::proto::processor::Query;
+use crate::processor::{
+    ProcessingStep,
+    AsyncProcessor,
+    format_extracted_data,
+    format_section_data,
+    format_image_ocr,
+    format_slide_data,
+    format_speaker_notes,
+    optimize_image,
+    is_meaningful_text,
+    clean_text,
+    clean_structured_text,
+    strip_repeated_lines,
+    record_metadata
+};
+use crate::proto::processor::{Query, Attachment};
+use super::docx::read_docx;
+use super::image::ImageOcr;
+use super::media::EmbeddedImage;
+use super::pptx::read_pptx;
+use super::ole::{read_doc, read_ppt, PptText};
+use super::opendocument::read_opendocument;
+use super::rtf::read_rtf;
+
+/// Embedded images smaller than this on either side are icons or bullets, not worth OCR
+const MIN_IMAGE_SIDE: u32 = 32;
 
 pub struct OfficeProcessor;
 
 #[async_trait]
 impl AsyncProcessor for OfficeProcessor {
-    async fn process(&self, query: &mut Query, _config: &Config) -> Result<(), ProcessError> {
+    async fn process(&self, query: &mut Query, config: &Config) -> Result<(), ProcessError> {
         // Try to extract text directly from the document
-        let extracted_text = self.extract_text(Path::new(&query.file_path)).await?;
+        let path = PathBuf::from(&query.file_path);
+        // Extractors may add their own parts, which follow the main text
+        let first_part = query.prompt_parts.len();
+        let extracted_text = self.extract_text(&path, query, config).await?;
         if let Some(text) = extracted_text {
-            let cleaned_text = clean_text(&text);
-            if !cleaned_text.is_empty() {
-                query.prompt_parts.push(format_extracted_data(&cleaned_text));
+            // Each extractor cleans its own text, keeping any structure it produces
+            if !text.is_empty() {
+                query.prompt_parts.insert(first_part, format_extracted_data(&text));
             }
         } else {
             // If no text was extracted, try reading as plain text
@@ -49,66 +74,106 @@ impl ProcessingStep for OfficeProcessor {
 }
 
 impl OfficeProcessor {
-    async fn extract_text(&self, path: &Path) -> Result<Option<String>, ProcessError> {
+    async fn extract_text(&self, path: &Path, query: &mut Query, config: &Config) -> Result<Option<String>, ProcessError> {
         let extension = path.extension()
             .and_then(|ext| ext.to_str())
             .ok_or_else(|| ProcessError::InvalidFormat("No file extension".to_string()))?;
         
         match extension.to_lowercase().as_str() {
-            "docx" => self.extract_docx(path),
+            "docx" => self.extract_docx(path, query, config),
+            // Older tools often save RTF or OOXML under a .doc name
+            "doc" => match leading_bytes(path)? {
+                bytes if bytes.starts_with(b"{\\rtf") => self.extract_rtf(path),
+                bytes if bytes.starts_with(b"PK") => self.extract_docx(path, query, config),
+                _ => self.extract_doc(path, query),
+            },
             "rtf" => self.extract_rtf(path),
-            "pptx" => self.extract_pptx(path),
+            "pptx" => self.extract_pptx(path, query, config),
+            "ppt" => self.extract_ppt(path, query),
+            "odt" => self.extract_opendocument(path),
+            "odp" => self.extract_odp(path, query),
             // Try plain text for other formats
             _ => Ok(None),
         }
     }
 
-    fn extract_docx(&self, path: &Path) -> Result<Option<String>, ProcessError> {
-        let file = std::fs::File::open(path)
-            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        
-        let mut archive = ZipArchive::new(file)
-            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        
-        // Find and read document.xml
-        let mut content = String::new();
-        if let Ok(mut doc) = archive.by_name("word/document.xml") {
-            doc.read_to_string(&mut content)
-                .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        } else {
+    fn extract_docx(&self, path: &Path, query: &mut Query, config: &Config) -> Result<Option<String>, ProcessError> {
+        let Some(docx) = read_docx(path, config.tracked_changes)? else {
             return Ok(None);
+        };
+
+        if docx.revisions > 0 {
+            record_metadata(query, "tracked_changes", docx.revisions.to_string());
         }
-        
-        // Parse XML and extract text
-        let mut reader = Reader::from_str(&content);
-        reader.trim_text(true);
-        
-        let mut text = String::new();
-        let mut buf = Vec::new();
-        let mut in_text = false;
-        
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Ok(Event::Start(ref e)) if e.name().as_ref() == b"w:t" => {
-                    in_text = true;
+
+        for (kind, text) in docx.sections {
+            let text = clean_structured_text(&text);
+            if !text.is_empty() {
+                query.prompt_parts.push(format_section_data(&text, kind));
+            }
+        }
+        self.process_images(docx.images, query, config);
+        Ok(join_pages(docx.pages, query, clean_structured_text))
+    }
+
+    /// Attach embedded images and add the text Tesseract finds in them, tied to their page or slide
+    fn process_images(&self, images: Vec<EmbeddedImage>, query: &mut Query, config: &Config) {
+        if images.is_empty() {
+            return;
+        }
+        // Images are still attached when Tesseract can't start
+        let mut ocr = ImageOcr::new(config)
+            .map_err(|e| warn!("OCR unavailable for embedded images: {}", e))
+            .ok();
+        for image in images {
+            // Formats the image crate can't decode, such as EMF and WMF, are left out
+            let img = match image::load_from_memory(&image.data) {
+                Ok(img) if img.width() >= MIN_IMAGE_SIDE && img.height() >= MIN_IMAGE_SIDE => img,
+                Ok(_) => continue,
+                Err(e) => {
+                    debug!("Skipping embedded image {}: {}", image.number, e);
+                    continue;
                 }
-                Ok(Event::Text(e)) if in_text => {
-                    if let Ok(t) = e.unescape() {
-                        text.push_str(&t);
-                        text.push(' ');
-                    }
+            };
+            let (optimized, buffer) = match optimize_image(&img, config.max_image_size_mb) {
+                Ok(optimized) => optimized,
+                Err(e) => {
+                    warn!("Failed to optimize embedded image {}: {}", image.number, e);
+                    continue;
                 }
-                Ok(Event::End(ref e)) if e.name().as_ref() == b"w:t" => {
-                    in_text = false;
+            };
+            query.attachments.push(Attachment {
+                page: image.page as i32,
+                data: buffer,
+            });
+
+            let Some(ocr) = ocr.as_mut() else {
+                continue;
+            };
+            match ocr.recognize(&optimized, &format!("image_{}.png", image.number), config) {
+                Ok(text) => {
+                    let text = clean_text(&text);
+                    if is_meaningful_text(&text, config.ocr_quality_threshold) {
+                        query.prompt_parts.push(format_image_ocr(&text, image.page as u32, image.number, image.description.as_deref()));
+                    } else {
+                        debug!("Text in embedded image {} not meaningful enough", image.number);
+                    }
                 }
-                Ok(Event::Eof) => break,
-                Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
-                _ => (),
+                Err(e) => warn!("OCR failed for embedded image {}: {}", image.number, e),
             }
-            buf.clear();
         }
-        
-        let text = clean_text(&text);
+    }
+
+    fn extract_doc(&self, path: &Path, query: &mut Query) -> Result<Option<String>, ProcessError> {
+        let pages = read_doc(path)?;
+        Ok(join_pages(pages, query, clean_text))
+    }
+
+    fn extract_rtf(&self, path: &Path) -> Result<Option<String>, ProcessError> {
+        let content = std::fs::read(path)
+            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+
+        let text = clean_structured_text(&read_rtf(&content));
         if !text.is_empty() {
             Ok(Some(text))
         } else {
@@ -116,83 +181,200 @@ impl OfficeProcessor {
         }
     }
 
-    fn extract_rtf(&self, path: &Path) -> Result<Option<String>, ProcessError> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        
-        // For RTF, replace \par with newlines and strip other RTF tags
-        let text = content
-            .replace("\\par", "\n")  // Replace paragraph markers with newlines
-            .lines()
-            .filter(|line| !line.starts_with("{") && !line.starts_with("}") && !line.starts_with("\\"))
-            .collect::<Vec<_>>()
-            .join("\n");
-        
-        let text = clean_text(&text);
-        if !text.is_empty() {
-            Ok(Some(text))
+    fn extract_pptx(&self, path: &Path, query: &mut Query, config: &Config) -> Result<Option<String>, ProcessError> {
+        let pptx = read_pptx(path)?;
+        record_metadata(query, "slides", pptx.slides.len().to_string());
+
+        let mut found_text = false;
+        for (index, slide) in pptx.slides.iter().enumerate() {
+            let mut text = clean_structured_text(&slide.text);
+            let notes = clean_structured_text(&slide.notes);
+            if !notes.is_empty() {
+                text = format!("{}\n{}", text, format_speaker_notes(&notes)).trim_start().to_string();
+            }
+            if text.is_empty() && slide.title.is_none() {
+                continue;
+            }
+            query.prompt_parts.push(format_slide_data(&text, index + 1, slide.title.as_deref()));
+            found_text = true;
+        }
+        self.process_images(pptx.images, query, config);
+
+        // Each slide is its own part, so there is no main text
+        if found_text {
+            Ok(Some(String::new()))
         } else {
             Ok(None)
         }
     }
 
-    fn extract_pptx(&self, path: &Path) -> Result<Option<String>, ProcessError> {
-        let file = std::fs::File::open(path)
-            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        
-        let mut archive = ZipArchive::new(file)
-            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-        
-        // Find and read slides
-        let mut text = String::new();
-        
-        for i in 0..archive.len() {
-            let file = archive.by_index(i)
-                .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
-            
-            let name = file.name().to_string();
-            if name.starts_with("ppt/slides/slide") && name.ends_with(".xml") {
-                let mut content = String::new();
-                let buf_reader = BufReader::new(file);
-                let mut reader = Reader::from_reader(buf_reader);
-                reader.trim_text(true);
-                
-                let mut buf = Vec::new();
-                let mut in_text = false;
-                
-                loop {
-                    match reader.read_event_into(&mut buf) {
-                        Ok(Event::Start(ref e)) if e.name().as_ref() == b"a:t" => {
-                            in_text = true;
-                        }
-                        Ok(Event::Text(e)) if in_text => {
-                            if let Ok(t) = e.unescape() {
-                                content.push_str(&t);
-                                content.push(' ');
-                            }
-                        }
-                        Ok(Event::End(ref e)) if e.name().as_ref() == b"a:t" => {
-                            in_text = false;
-                        }
-                        Ok(Event::Eof) => break,
-                        Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
-                        _ => (),
-                    }
-                    buf.clear();
-                }
-                
-                if !content.is_empty() {
-                    text.push_str(&content);
-                    text.push('\n');
+    fn extract_ppt(&self, path: &Path, query: &mut Query) -> Result<Option<String>, ProcessError> {
+        match read_ppt(path)? {
+            PptText::Slides(slides) => Ok(push_slides(&slides, query, clean_text)),
+            PptText::Unordered(text) => {
+                let text = clean_text(&text);
+                if !text.is_empty() {
+                    Ok(Some(text))
+                } else {
+                    Ok(None)
                 }
             }
         }
-        
-        let text = clean_text(&text);
+    }
+
+    fn extract_odp(&self, path: &Path, query: &mut Query) -> Result<Option<String>, ProcessError> {
+        let slides = read_opendocument(path)?;
+        Ok(push_slides(&slides, query, clean_structured_text))
+    }
+
+    fn extract_opendocument(&self, path: &Path) -> Result<Option<String>, ProcessError> {
+        let pages = read_opendocument(path)?;
+        let text = clean_structured_text(&pages.join("\n\n"));
         if !text.is_empty() {
             Ok(Some(text))
         } else {
             Ok(None)
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Add each slide with text as a tagged part, numbered by its place in the deck
+fn push_slides(slides: &[String], query: &mut Query, clean: fn(&str) -> String) -> Option<String> {
+    record_metadata(query, "slides", slides.len().to_string());
+
+    let mut found_text = false;
+    for (index, slide) in slides.iter().enumerate() {
+        let text = clean(slide);
+        if !text.is_empty() {
+            query.prompt_parts.push(format_slide_data(&text, index + 1, None));
+            found_text = true;
+        }
+    }
+
+    // Each slide is its own part, so there is no main text
+    found_text.then(String::new)
+}
+
+/// Join page texts after dropping running headers, footers and page numbers typed into the body
+fn join_pages(mut pages: Vec<String>, query: &mut Query, clean: fn(&str) -> String) -> Option<String> {
+    let removed = strip_repeated_lines(&mut pages);
+    if !removed.is_empty() {
+        record_metadata(query, "repeated_lines_removed", removed.join("\n"));
+    }
+
+    let text = clean(&pages.join("\n"));
+    if !text.is_empty() {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// The first few bytes of a file, to tell formats apart by their signature
+fn leading_bytes(path: &Path) -> Result<Vec<u8>, ProcessError> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)?.take(8).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn write_package(path: &Path, parts: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in parts {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn query(path: &Path, file_type: &str) -> Query {
+        Query {
+            file_type: file_type.to_string(),
+            file_path: path.to_string_lossy().into_owned(),
+            strategy: Strategy::Office.to_string(),
+            prompt_parts: Vec::new(),
+            attachments: Vec::new(),
+            system: String::new(),
+            prompt: String::new(),
+            metadata: Some(Default::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_docx_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sections.docx");
+        write_package(&path, &[
+            ("word/document.xml", r#"<w:document><w:body><w:p><w:r><w:t>Terms</w:t></w:r><w:r><w:footnoteReference w:id="1"/></w:r></w:p></w:body></w:document>"#),
+            ("word/header1.xml", r#"<w:hdr><w:p><w:r><w:t>Acme Ltd</w:t></w:r></w:p></w:hdr>"#),
+            ("word/footer1.xml", r#"<w:ftr><w:p><w:r><w:t>Confidential</w:t></w:r></w:p></w:ftr>"#),
+            ("word/footnotes.xml", r#"<w:footnotes><w:footnote w:id="1"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> Net of tax.</w:t></w:r></w:p></w:footnote></w:footnotes>"#),
+        ]);
+
+        let mut query = query(&path, "docx");
+        OfficeProcessor.process(&mut query, &Config::default()).await.unwrap();
+        assert_eq!(query.prompt_parts, vec![
+            "<EXTRACTED_DATA>Terms[1]</EXTRACTED_DATA>",
+            "<SECTION TYPE=\"headers\">Acme Ltd</SECTION>",
+            "<SECTION TYPE=\"footers\">Confidential</SECTION>",
+            "<SECTION TYPE=\"footnotes\">[1] Net of tax.</SECTION>",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_odp_slides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deck.odp");
+        write_package(&path, &[
+            ("content.xml", r#"<office:document-content><office:body><office:presentation>
+                <draw:page><draw:frame><draw:text-box><text:p>Intro</text:p></draw:text-box></draw:frame></draw:page>
+                <draw:page/>
+                <draw:page><draw:frame><draw:text-box><text:p>Results</text:p></draw:text-box></draw:frame></draw:page>
+            </office:presentation></office:body></office:document-content>"#),
+        ]);
+
+        let mut query = query(&path, "odp");
+        OfficeProcessor.process(&mut query, &Config::default()).await.unwrap();
+        assert_eq!(query.prompt_parts, vec!["<SLIDE NUMBER=1>Intro</SLIDE>", "<SLIDE NUMBER=3>Results</SLIDE>"]);
+    }
+
+    #[tokio::test]
+    async fn test_ppt_slides() {
+        fn record(version: u16, kind: u16, body: &[u8]) -> Vec<u8> {
+            let mut data = [version.to_le_bytes(), kind.to_le_bytes()].concat();
+            data.extend((body.len() as u32).to_le_bytes());
+            data.extend(body);
+            data
+        }
+        let persist = |id: u32| record(0, 0x03F3, &id.to_le_bytes());
+        let text = |text: &str| record(0, 0x0FA8, text.as_bytes());
+
+        // Three slides, the second without text, listed in a document container at offset 0
+        let slide_list = [persist(2), text("Intro"), persist(3), persist(4), text("Results")].concat();
+        let mut document = record(0x0F, 0x03E8, &record(0x0F, 0x0FF0, &slide_list));
+        let directory_offset = document.len() as u32;
+        document.extend(record(0, 0x1772, &[(1u32 | 1 << 20).to_le_bytes(), 0u32.to_le_bytes()].concat()));
+        let edit_offset = document.len() as u32;
+        let edit = [[0u8; 8].as_slice(), &0u32.to_le_bytes(), &directory_offset.to_le_bytes(), &1u32.to_le_bytes()].concat();
+        document.extend(record(0, 0x0FF5, &edit));
+        let current_user = [[0u8; 16].as_slice(), &edit_offset.to_le_bytes()].concat();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deck.ppt");
+        let mut file = cfb::create(&path).unwrap();
+        file.create_stream("/PowerPoint Document").unwrap().write_all(&document).unwrap();
+        file.create_stream("/Current User").unwrap().write_all(&current_user).unwrap();
+        file.flush().unwrap();
+        drop(file);
+
+        let mut query = query(&path, "ppt");
+        OfficeProcessor.process(&mut query, &Config::default()).await.unwrap();
+        assert_eq!(query.prompt_parts, vec!["<SLIDE NUMBER=1>Intro</SLIDE>", "<SLIDE NUMBER=3>Results</SLIDE>"]);
+    }
+}
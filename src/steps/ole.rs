@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use cfb::CompoundFile;
+use encoding_rs::{UTF_16LE, WINDOWS_1252};
+use crate::types::ProcessError;
+
+/// `wIdent` of a Word binary File Information Block
+const WORD_IDENT: u16 = 0xA5EC;
+const FIB_ENCRYPTED: u16 = 0x0100;
+const FIB_WHICH_TABLE: u16 = 0x0200;
+/// Index of the `fcClx`/`lcbClx` pair in `FibRgFcLcb97`
+const FC_CLX_INDEX: usize = 33;
+/// Index of the `fcPlcfBtePapx`/`lcbPlcfBtePapx` pair in `FibRgFcLcb97`
+const FC_PLCF_BTE_PAPX_INDEX: usize = 13;
+/// Size of a formatted disk page of paragraph properties
+const FKP_SIZE: usize = 512;
+
+// Paragraph and table property modifiers
+const SPRM_P_F_TTP: u16 = 0x2417;
+const SPRM_T_DEF_TABLE: u16 = 0xD608;
+
+// PowerPoint record types
+const SLIDE_PERSIST_ATOM: u16 = 0x03F3;
+const TEXT_CHARS_ATOM: u16 = 0x0FA0;
+const TEXT_BYTES_ATOM: u16 = 0x0FA8;
+const SLIDE_LIST_WITH_TEXT: u16 = 0x0FF0;
+const USER_EDIT_ATOM: u16 = 0x0FF5;
+const PERSIST_DIRECTORY_ATOM: u16 = 0x1772;
+
+/// A run of document text stored contiguously in the WordDocument stream
+struct Piece {
+    cp_start: usize,
+    cp_end: usize,
+    offset: usize,
+    compressed: bool,
+}
+
+/// A PowerPoint record header and body
+struct Record<'a> {
+    kind: u16,
+    instance: u16,
+    container: bool,
+    body: &'a [u8],
+}
+
+/// Main document text of a Word 97-2003 file, split into pages at page and section breaks
+pub(crate) fn read_doc(path: &Path) -> Result<Vec<String>, ProcessError> {
+    let mut file = open(path)?;
+    let word = read_stream(&mut file, "/WordDocument")?
+        .ok_or_else(|| ProcessError::InvalidFormat("Missing WordDocument stream".to_string()))?;
+    if u16_at(&word, 0) != Some(WORD_IDENT) {
+        return Err(ProcessError::InvalidFormat("Not a Word binary document".to_string()));
+    }
+
+    let flags = u16_at(&word, 0x0A).unwrap_or(0);
+    if flags & FIB_ENCRYPTED != 0 {
+        return Err(ProcessError::ExtractionFailed("Document is encrypted".to_string()));
+    }
+    let table_stream = if flags & FIB_WHICH_TABLE != 0 { "/1Table" } else { "/0Table" };
+    let table = read_stream(&mut file, table_stream)?.unwrap_or_default();
+
+    // Files without a piece table (Word 6 and earlier) keep their text in one 8-bit run
+    let (text, row_ends) = piece_table_text(&word, &table).unwrap_or_else(|| {
+        let start = u32_at(&word, 0x18).unwrap_or(0) as usize;
+        let end = u32_at(&word, 0x1C).unwrap_or(0) as usize;
+        let bytes = word.get(start..end).unwrap_or_default();
+        (WINDOWS_1252.decode_without_bom_handling(bytes).0.into_owned(), HashSet::new())
+    });
+    Ok(doc_pages(&text, &row_ends))
+}
+
+/// Text of a PowerPoint 97-2003 file
+pub(crate) enum PptText {
+    /// Each slide of the SlideListWithText, in presentation order
+    Slides(Vec<String>),
+    /// Every text atom in stream order, when the slide list can't be reached
+    Unordered(String),
+}
+
+/// Text of each slide of a PowerPoint 97-2003 file, in presentation order
+pub(crate) fn read_ppt(path: &Path) -> Result<PptText, ProcessError> {
+    let mut file = open(path)?;
+    let document = read_stream(&mut file, "/PowerPoint Document")?
+        .ok_or_else(|| ProcessError::InvalidFormat("Missing PowerPoint Document stream".to_string()))?;
+    let current_user = read_stream(&mut file, "/Current User")?.unwrap_or_default();
+
+    // Without a usable edit history, fall back to every text atom in stream order
+    match slide_texts(&document, &current_user) {
+        Some(slides) => Ok(PptText::Slides(slides)),
+        None => {
+            let mut text = Vec::new();
+            collect_text_atoms(&document, &mut text);
+            Ok(PptText::Unordered(text.join("\n")))
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<CompoundFile<File>, ProcessError> {
+    cfb::open(path).map_err(|e| ProcessError::InvalidFormat(format!("Not an OLE compound file: {}", e)))
+}
+
+fn read_stream(file: &mut CompoundFile<File>, name: &str) -> Result<Option<Vec<u8>>, ProcessError> {
+    if !file.is_stream(name) {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    file.open_stream(name)?.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Offset and size of the structure a `FibRgFcLcb97` entry locates in the table stream
+fn fib_fc_lcb(word: &[u8], index: usize) -> Option<(usize, usize)> {
+    // The FIB is a fixed base followed by three counted arrays
+    let csw = u16_at(word, 32)? as usize;
+    let rg_lw = 34 + csw * 2;
+    let cslw = u16_at(word, rg_lw)? as usize;
+    let rg_fc_lcb = rg_lw + 2 + cslw * 4;
+    if (u16_at(word, rg_fc_lcb)? as usize) <= index {
+        return None;
+    }
+    let fc = u32_at(word, rg_fc_lcb + 2 + index * 8)? as usize;
+    let lcb = u32_at(word, rg_fc_lcb + 2 + index * 8 + 4)? as usize;
+    Some((fc, lcb))
+}
+
+/// The main document text, read piece by piece through the Clx in the table stream, along with
+/// the char indices of the cell marks that end a table row
+fn piece_table_text(word: &[u8], table: &[u8]) -> Option<(String, HashSet<usize>)> {
+    let csw = u16_at(word, 32)? as usize;
+    let ccp_text = u32_at(word, 34 + csw * 2 + 2 + 12)? as usize;
+    let (fc_clx, lcb_clx) = fib_fc_lcb(word, FC_CLX_INDEX)?;
+    let clx = table.get(fc_clx..fc_clx.checked_add(lcb_clx)?)?;
+    let row_paragraphs = row_end_paragraphs(word, table).unwrap_or_default();
+
+    let mut text = String::new();
+    let mut row_ends = HashSet::new();
+    let mut index = 0;
+    for piece in parse_clx(clx)? {
+        if piece.cp_start >= ccp_text {
+            break;
+        }
+        let chars = piece.cp_end.min(ccp_text).saturating_sub(piece.cp_start);
+        let (decoded, width) = if piece.compressed {
+            let bytes = word.get(piece.offset..piece.offset + chars)?;
+            (WINDOWS_1252.decode_without_bom_handling(bytes).0, 1)
+        } else {
+            let bytes = word.get(piece.offset..piece.offset + chars * 2)?;
+            (UTF_16LE.decode_without_bom_handling(bytes).0, 2)
+        };
+
+        let mut offset = piece.offset;
+        for c in decoded.chars() {
+            if c == '\u{07}' && within(&row_paragraphs, offset) {
+                row_ends.insert(index);
+            }
+            text.push(c);
+            index += 1;
+            offset += width * c.len_utf16();
+        }
+    }
+    Some((text, row_ends))
+}
+
+/// Whether an offset falls in one of a sorted list of non-overlapping ranges
+fn within(ranges: &[(usize, usize)], offset: usize) -> bool {
+    let next = ranges.partition_point(|(start, _)| *start <= offset);
+    next > 0 && offset < ranges[next - 1].1
+}
+
+/// File offset ranges of the paragraphs that end a table row, from the paragraph property pages
+fn row_end_paragraphs(word: &[u8], table: &[u8]) -> Option<Vec<(usize, usize)>> {
+    let (fc, lcb) = fib_fc_lcb(word, FC_PLCF_BTE_PAPX_INDEX)?;
+    let plc = table.get(fc..fc.checked_add(lcb)?)?;
+    let count = lcb.checked_sub(4)? / 8;
+    let mut ranges = Vec::new();
+    for i in 0..count {
+        let page_number = (u32_at(plc, (count + 1) * 4 + i * 4)? & 0x003F_FFFF) as usize;
+        let page = word.get(page_number * FKP_SIZE..(page_number + 1) * FKP_SIZE)?;
+        ranges.extend(fkp_row_ends(page));
+    }
+    ranges.sort_unstable();
+    Some(ranges)
+}
+
+/// Paragraphs of one `PapxFkp` page whose properties mark them as table row ends
+fn fkp_row_ends(page: &[u8]) -> Vec<(usize, usize)> {
+    let runs = page[FKP_SIZE - 1] as usize;
+    (0..runs)
+        .filter_map(|i| {
+            let start = u32_at(page, i * 4)? as usize;
+            let end = u32_at(page, (i + 1) * 4)? as usize;
+            let papx = *page.get((runs + 1) * 4 + i * 13)? as usize * 2;
+            if papx == 0 {
+                return None;
+            }
+            let properties = match *page.get(papx)? {
+                0 => {
+                    let size = *page.get(papx + 1)? as usize * 2;
+                    page.get(papx + 2..papx + 2 + size)?
+                }
+                cb => page.get(papx + 1..papx + cb as usize * 2)?,
+            };
+            // The paragraph's style index comes before its property modifiers
+            is_row_end(properties.get(2..)?).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Whether a paragraph's property modifiers set `sprmPFTtp`
+fn is_row_end(mut prls: &[u8]) -> bool {
+    while let Some(sprm) = u16_at(prls, 0) {
+        if sprm == SPRM_P_F_TTP {
+            return prls.get(2) == Some(&1);
+        }
+        // The top three bits give the operand size, or say its first byte holds it
+        let operand = match sprm >> 13 {
+            0 | 1 => 1,
+            2 | 4 | 5 => 2,
+            3 => 4,
+            7 => 3,
+            // sprmTDefTable counts its size in two bytes, one more than follows them
+            _ if sprm == SPRM_T_DEF_TABLE => match u16_at(prls, 2) {
+                Some(cb) => cb as usize + 1,
+                None => return false,
+            },
+            _ => match prls.get(2) {
+                Some(&cb) => cb as usize + 1,
+                None => return false,
+            },
+        };
+        prls = match prls.get(2 + operand..) {
+            Some(rest) => rest,
+            None => return false,
+        };
+    }
+    false
+}
+
+/// Pieces of the `Pcdt` in a Clx, skipping any leading property modifiers
+fn parse_clx(clx: &[u8]) -> Option<Vec<Piece>> {
+    let mut pos = 0;
+    while clx.get(pos) == Some(&0x01) {
+        pos += 3 + u16_at(clx, pos + 1)? as usize;
+    }
+    if clx.get(pos) != Some(&0x02) {
+        return None;
+    }
+
+    let lcb = u32_at(clx, pos + 1)? as usize;
+    let plc = clx.get(pos + 5..(pos + 5).checked_add(lcb)?)?;
+    let count = lcb.checked_sub(4)? / 12;
+    (0..count)
+        .map(|i| {
+            let fc = u32_at(plc, (count + 1) * 4 + i * 8 + 2)?;
+            let compressed = fc & 0x4000_0000 != 0;
+            let fc = (fc & 0x3FFF_FFFF) as usize;
+            Some(Piece {
+                cp_start: u32_at(plc, i * 4)? as usize,
+                cp_end: u32_at(plc, (i + 1) * 4)? as usize,
+                offset: if compressed { fc / 2 } else { fc },
+                compressed,
+            })
+        })
+        .collect()
+}
+
+/// Map Word's control characters to plain text and split at page and section breaks
+///
+/// Each cell ends with a mark, and each row with one more, found at the char indices in `row_ends`.
+fn doc_pages(text: &str, row_ends: &HashSet<usize>) -> Vec<String> {
+    let mut pages = vec![String::new()];
+    // One entry per open field, set once its result starts
+    let mut fields: Vec<bool> = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        match c {
+            '\u{13}' => fields.push(false),
+            '\u{14}' => {
+                if let Some(showing) = fields.last_mut() {
+                    *showing = true;
+                }
+            }
+            '\u{15}' => {
+                fields.pop();
+            }
+            // Field codes such as `PAGE` or `HYPERLINK "..."` are hidden behind their results
+            _ if fields.iter().any(|showing| !showing) => (),
+            '\u{0C}' => pages.push(String::new()),
+            c => {
+                let Some(page) = pages.last_mut() else {
+                    continue;
+                };
+                match c {
+                    '\r' | '\u{0B}' => page.push('\n'),
+                    // The row break takes the place of the last cell's tab
+                    '\u{07}' if row_ends.contains(&index) => {
+                        if page.ends_with('\t') {
+                            page.pop();
+                        }
+                        page.push('\n');
+                    }
+                    '\u{07}' | '\t' => page.push('\t'),
+                    '\u{1E}' => page.push('-'),
+                    '\u{A0}' => page.push(' '),
+                    c if c < ' ' => (),
+                    c => page.push(c),
+                }
+            }
+        }
+    }
+    pages
+}
+
+fn record_at(data: &[u8], offset: usize) -> Option<Record<'_>> {
+    let version = u16_at(data, offset)?;
+    let kind = u16_at(data, offset + 2)?;
+    let length = u32_at(data, offset + 4)? as usize;
+    let start = offset.checked_add(8)?;
+    let body = data.get(start..start.checked_add(length)?.min(data.len()))?;
+    Some(Record { kind, instance: version >> 4, container: version & 0x0F == 0x0F, body })
+}
+
+/// The records directly inside a container's body
+fn children(body: &[u8]) -> impl Iterator<Item = Record<'_>> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let record = record_at(body, offset)?;
+        offset += 8 + record.body.len();
+        Some(record)
+    })
+}
+
+/// Plain text of a TextCharsAtom or TextBytesAtom
+fn atom_text(record: &Record) -> Option<String> {
+    let text = match record.kind {
+        TEXT_CHARS_ATOM => UTF_16LE.decode_without_bom_handling(record.body).0.into_owned(),
+        // Each byte is the low byte of a UTF-16 code unit
+        TEXT_BYTES_ATOM => record.body.iter().map(|&b| b as char).collect(),
+        _ => return None,
+    };
+    Some(text.replace(['\r', '\u{0B}'], "\n"))
+}
+
+/// Text atoms anywhere inside `body`, in stream order
+fn collect_text_atoms(body: &[u8], lines: &mut Vec<String>) {
+    for record in children(body) {
+        if record.container {
+            collect_text_atoms(record.body, lines);
+        } else if let Some(text) = atom_text(&record) {
+            lines.push(text);
+        }
+    }
+}
+
+/// Slide text found through the current edit's persist directory
+fn slide_texts(document: &[u8], current_user: &[u8]) -> Option<Vec<String>> {
+    let (directory, document_ref) = persist_directory(document, u32_at(current_user, 16)? as usize)?;
+    let container = record_at(document, *directory.get(&document_ref)?)?;
+    let slide_list = children(container.body).find(|r| r.kind == SLIDE_LIST_WITH_TEXT && r.instance == 0)?;
+
+    // Placeholder text is kept in the slide list, after each slide's persist atom
+    let mut slides: Vec<(Option<u32>, Vec<String>)> = Vec::new();
+    for record in children(slide_list.body) {
+        if record.kind == SLIDE_PERSIST_ATOM {
+            slides.push((u32_at(record.body, 0), Vec::new()));
+        } else if let (Some(text), Some((_, lines))) = (atom_text(&record), slides.last_mut()) {
+            lines.push(text);
+        }
+    }
+
+    // Other text boxes live in the slide's own drawing
+    let texts = slides.into_iter()
+        .map(|(persist_ref, mut lines)| {
+            let slide = persist_ref.and_then(|id| directory.get(&id)).and_then(|&offset| record_at(document, offset));
+            if let Some(slide) = slide {
+                let mut drawing = Vec::new();
+                collect_text_atoms(slide.body, &mut drawing);
+                let seen: HashSet<String> = lines.iter().cloned().collect();
+                lines.extend(drawing.into_iter().filter(|text| !seen.contains(text)));
+            }
+            lines.join("\n")
+        })
+        .collect();
+    Some(texts)
+}
+
+/// Object offsets by persist id, merged over every saved edit, and the document's persist id
+fn persist_directory(document: &[u8], mut edit_offset: usize) -> Option<(HashMap<u32, usize>, u32)> {
+    let mut directories = Vec::new();
+    let mut document_ref = None;
+    let mut seen = HashSet::new();
+    while seen.insert(edit_offset) {
+        let edit = record_at(document, edit_offset)?;
+        if edit.kind != USER_EDIT_ATOM {
+            return None;
+        }
+        document_ref.get_or_insert(u32_at(edit.body, 16)?);
+        directories.push(u32_at(edit.body, 12)? as usize);
+        match u32_at(edit.body, 8)? {
+            0 => break,
+            previous => edit_offset = previous as usize,
+        }
+    }
+
+    // Oldest edit first so later saves override earlier offsets
+    let mut offsets = HashMap::new();
+    for offset in directories.into_iter().rev() {
+        let atom = record_at(document, offset)?;
+        if atom.kind != PERSIST_DIRECTORY_ATOM {
+            return None;
+        }
+        let mut pos = 0;
+        while let Some(entry) = u32_at(atom.body, pos) {
+            let (first, count) = (entry & 0xF_FFFF, entry >> 20);
+            pos += 4;
+            for id in first..first + count {
+                offsets.insert(id, u32_at(atom.body, pos)? as usize);
+                pos += 4;
+            }
+        }
+    }
+    Some((offsets, document_ref?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_pages() {
+        // A row of three cells, the middle one empty
+        let text = "Title\r\u{13} PAGE \u{14}3\u{15} of 9\ra\u{07}\u{07}c\u{07}\u{07}\u{0C}Next\u{1E}page\r";
+        let row_end = text.chars().position(|c| c == '\u{0C}').unwrap() - 1;
+        assert_eq!(doc_pages(text, &HashSet::from([row_end])), vec!["Title\n3 of 9\na\t\tc\n", "Next-page\n"]);
+    }
+
+    #[test]
+    fn test_fkp_row_ends() {
+        // Two paragraphs: an in-table cell, then a row end whose sprmTDefTable comes before sprmPFTtp
+        let mut page = vec![0u8; FKP_SIZE];
+        for (i, fc) in [0x400u32, 0x410, 0x420].into_iter().enumerate() {
+            page[i * 4..i * 4 + 4].copy_from_slice(&fc.to_le_bytes());
+        }
+        page[12] = 0x80;
+        page[25] = 0x88;
+        page[FKP_SIZE - 1] = 2;
+        page[0x100..0x106].copy_from_slice(&[3, 0, 0, 0x16, 0x24, 1]);
+        page[0x110..0x11C].copy_from_slice(&[0, 6, 0, 0, 0x08, 0xD6, 3, 0, 0xAA, 0xBB, 0x17, 0x24]);
+        page[0x11C] = 1;
+
+        assert_eq!(fkp_row_ends(&page), vec![(0x410, 0x420)]);
+        assert!(within(&[(0x410, 0x420)], 0x41E));
+        assert!(!within(&[(0x410, 0x420)], 0x420));
+    }
+
+    #[test]
+    fn test_parse_clx() {
+        // One property modifier, then two pieces: compressed at byte 0x800 and UTF-16 at 0x1000
+        let mut clx = vec![0x01, 0x02, 0x00, 0xAA, 0xBB, 0x02];
+        clx.extend(28u32.to_le_bytes());
+        for cp in [0u32, 10, 15] {
+            clx.extend(cp.to_le_bytes());
+        }
+        for fc in [0x4000_1000u32, 0x1000] {
+            clx.extend([0, 0]);
+            clx.extend(fc.to_le_bytes());
+            clx.extend([0, 0]);
+        }
+
+        let pieces = parse_clx(&clx).unwrap();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!((pieces[0].cp_start, pieces[0].cp_end, pieces[0].offset, pieces[0].compressed), (0, 10, 0x800, true));
+        assert_eq!((pieces[1].cp_start, pieces[1].cp_end, pieces[1].offset, pieces[1].compressed), (10, 15, 0x1000, false));
+    }
+}
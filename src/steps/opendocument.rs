@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use zip::ZipArchive;
+use crate::types::ProcessError;
+use super::table::{flatten_table, markdown_table, push_line};
+use super::package::{attribute, read_part};
+
+/// Most columns a repeated table cell expands to
+const MAX_REPEATED_CELLS: usize = 64;
+/// Most spaces a `text:s` run expands to
+const MAX_SPACE_RUN: usize = 64;
+
+/// An open `text:list`, with whether each of its items is numbered
+struct List {
+    numbered: bool,
+    items: usize,
+}
+
+/// An open `table:table`, collecting its cells as text
+#[derive(Default)]
+struct Table {
+    rows: Vec<Vec<String>>,
+    repeat: usize,
+}
+
+/// A `text:note` being read, with its citation and body text
+#[derive(Default)]
+struct Note {
+    citation: String,
+    body: Vec<String>,
+}
+
+/// Text of an OpenDocument file as Markdown: one entry per slide for presentations, a single entry otherwise
+pub(crate) fn read_opendocument(path: &Path) -> Result<Vec<String>, ProcessError> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+    let content = read_part(&mut archive, "content.xml")?
+        .ok_or_else(|| ProcessError::InvalidFormat("Missing content.xml".to_string()))?;
+
+    // Named list styles live in styles.xml, automatic ones in content.xml
+    let mut list_styles = HashMap::new();
+    if let Some(styles) = read_part(&mut archive, "styles.xml")? {
+        list_styles.extend(parse_list_styles(&styles)?);
+    }
+    list_styles.extend(parse_list_styles(&content)?);
+
+    parse_content(&content, &list_styles)
+}
+
+/// Whether each level of each list style is numbered rather than bulleted, by style name
+fn parse_list_styles(content: &str) -> Result<HashMap<String, Vec<bool>>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut styles: HashMap<String, Vec<bool>> = HashMap::new();
+    let mut current: Option<String> = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                b"text:list-style" => current = attribute(e, b"style:name"),
+                level @ (b"text:list-level-style-number" | b"text:list-level-style-bullet" | b"text:list-level-style-image") => {
+                    let depth = attribute(e, b"text:level").and_then(|l| l.parse::<usize>().ok()).unwrap_or(1);
+                    if let (Some(name), Some(index)) = (&current, depth.checked_sub(1)) {
+                        let levels = styles.entry(name.clone()).or_default();
+                        if levels.len() <= index {
+                            levels.resize(index + 1, false);
+                        }
+                        levels[index] = level == b"text:list-level-style-number";
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"text:list-style" => current = None,
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(styles)
+}
+
+/// Walk `content.xml`, keeping headings, lists and tables as Markdown
+fn parse_content(content: &str, list_styles: &HashMap<String, Vec<bool>>) -> Result<Vec<String>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut skip_buf = Vec::new();
+
+    let mut slides: Vec<String> = Vec::new();
+    let mut blocks: Vec<String> = Vec::new();
+    // Paragraphs nest when a frame sits inside one
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut lists: Vec<(Option<String>, List)> = Vec::new();
+    let mut marker: Option<String> = None;
+    let mut tables: Vec<Table> = Vec::new();
+    let mut notes: Vec<String> = Vec::new();
+    let mut note: Option<Note> = None;
+    let mut in_citation = false;
+
+    loop {
+        let event = reader.read_event_into(&mut buf);
+        // Empty elements open and close at once
+        let empty = matches!(event, Ok(Event::Empty(_)));
+        match event {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                // Deleted text, comments and speaker notes are not part of the document body
+                name @ (b"text:tracked-changes" | b"office:annotation" | b"presentation:notes") if !empty => {
+                    reader.read_to_end_into(quick_xml::name::QName(name), &mut skip_buf)
+                        .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                }
+                // An empty page still takes its place in the slide numbering
+                b"draw:page" if empty => {
+                    blocks.clear();
+                    slides.push(String::new());
+                }
+                b"draw:page" => blocks.clear(),
+                b"text:p" | b"text:h" if !empty => {
+                    let mut prefix = String::new();
+                    if e.name().as_ref() == b"text:h" && tables.is_empty() && lists.is_empty() {
+                        let level = attribute(e, b"text:outline-level").and_then(|l| l.parse::<usize>().ok()).unwrap_or(1);
+                        prefix = format!("{} ", "#".repeat(level.clamp(1, 6)));
+                    }
+                    if let Some(marker) = marker.take() {
+                        prefix.push_str(&marker);
+                    } else if !lists.is_empty() && tables.is_empty() {
+                        // Later paragraphs of a list item line up under its text
+                        prefix.push_str(&"  ".repeat(lists.len()));
+                    }
+                    paragraphs.push(prefix);
+                }
+                b"text:list" if !empty => {
+                    let style = attribute(e, b"text:style-name")
+                        .or_else(|| lists.last().and_then(|(style, _)| style.clone()));
+                    let numbered = style.as_ref()
+                        .and_then(|style| list_styles.get(style))
+                        .and_then(|levels| levels.get(lists.len()).copied())
+                        .unwrap_or(false);
+                    lists.push((style, List { numbered, items: 0 }));
+                }
+                b"text:list-item" => {
+                    let depth = lists.len().saturating_sub(1);
+                    if let Some((_, list)) = lists.last_mut() {
+                        list.items += 1;
+                        let bullet = if list.numbered { format!("{}.", list.items) } else { "-".to_string() };
+                        marker = Some(format!("{}{} ", "  ".repeat(depth), bullet));
+                    }
+                }
+                b"text:s" => {
+                    let count = attribute(e, b"text:c").and_then(|c| c.parse::<usize>().ok()).unwrap_or(1);
+                    if let Some(paragraph) = paragraphs.last_mut() {
+                        paragraph.push_str(&" ".repeat(count.min(MAX_SPACE_RUN)));
+                    }
+                }
+                b"text:tab" => {
+                    if let Some(paragraph) = paragraphs.last_mut() {
+                        paragraph.push('\t');
+                    }
+                }
+                b"text:line-break" => {
+                    if let Some(paragraph) = paragraphs.last_mut() {
+                        paragraph.push('\n');
+                    }
+                }
+                b"text:note" if !empty => note = Some(Note::default()),
+                b"text:note-citation" if !empty => in_citation = true,
+                b"table:table" if !empty => tables.push(Table::default()),
+                b"table:table-row" => {
+                    if let Some(table) = tables.last_mut() {
+                        table.rows.push(Vec::new());
+                    }
+                }
+                b"table:table-cell" | b"table:covered-table-cell" => {
+                    let repeat = attribute(e, b"table:number-columns-repeated")
+                        .and_then(|r| r.parse::<usize>().ok())
+                        .unwrap_or(1)
+                        .clamp(1, MAX_REPEATED_CELLS);
+                    if let Some(row) = tables.last_mut().and_then(|table| table.rows.last_mut()) {
+                        row.push(String::new());
+                    }
+                    if let Some(table) = tables.last_mut() {
+                        table.repeat = if empty { 0 } else { repeat - 1 };
+                        if empty {
+                            if let Some(row) = table.rows.last_mut() {
+                                row.extend(std::iter::repeat_n(String::new(), repeat - 1));
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Text(e)) if !paragraphs.is_empty() => {
+                let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                match note.as_mut() {
+                    Some(note) if in_citation => note.citation.push_str(&text),
+                    _ => {
+                        if let Some(paragraph) = paragraphs.last_mut() {
+                            push_collapsed(paragraph, &text);
+                        }
+                    }
+                }
+            }
+            Ok(Event::End(ref e)) => match e.name().as_ref() {
+                b"text:p" | b"text:h" => {
+                    let Some(paragraph) = paragraphs.pop() else {
+                        continue;
+                    };
+                    let paragraph = paragraph.trim_end().to_string();
+                    let blank = paragraph.trim().is_empty();
+                    if blank {
+                        continue;
+                    }
+                    if let Some(note) = note.as_mut() {
+                        note.body.push(paragraph.trim().to_string());
+                    } else if let Some(cell) = tables.last_mut().and_then(|table| table.rows.last_mut()).and_then(|row| row.last_mut()) {
+                        push_line(cell, paragraph.trim());
+                    } else {
+                        blocks.push(paragraph);
+                    }
+                }
+                b"text:note-citation" => {
+                    in_citation = false;
+                    // The citation stays in the text as a reference to the note
+                    if let (Some(note), Some(paragraph)) = (note.as_ref(), paragraphs.last_mut()) {
+                        paragraph.push_str(&format!("[{}]", note.citation.trim()));
+                    }
+                }
+                b"text:note" => {
+                    if let Some(note) = note.take() {
+                        notes.push(format!("[{}] {}", note.citation.trim(), note.body.join(" ")));
+                    }
+                }
+                b"text:list" => {
+                    lists.pop();
+                    marker = None;
+                }
+                b"table:table-cell" | b"table:covered-table-cell" => {
+                    if let Some(table) = tables.last_mut() {
+                        let repeat = std::mem::take(&mut table.repeat);
+                        if let Some(row) = table.rows.last_mut() {
+                            let cell = row.last().cloned().unwrap_or_default();
+                            row.extend(std::iter::repeat_n(cell, repeat));
+                        }
+                    }
+                }
+                b"table:table" => {
+                    let Some(mut table) = tables.pop() else {
+                        continue;
+                    };
+                    // Trailing empty cells and rows are usually repeated padding
+                    for row in &mut table.rows {
+                        while row.last().is_some_and(|cell| cell.is_empty()) {
+                            row.pop();
+                        }
+                    }
+                    table.rows.retain(|row| !row.is_empty());
+                    let cell = tables.last_mut().and_then(|outer| outer.rows.last_mut()).and_then(|row| row.last_mut());
+                    match cell {
+                        Some(cell) => push_line(cell, &flatten_table(&table.rows)),
+                        None if !table.rows.is_empty() => blocks.push(markdown_table(&table.rows)),
+                        None => (),
+                    }
+                }
+                b"draw:page" => slides.push(std::mem::take(&mut blocks).join("\n")),
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    if slides.is_empty() {
+        blocks.extend(notes);
+        slides.push(blocks.join("\n"));
+    }
+    Ok(slides)
+}
+
+/// Append XML text, collapsing whitespace runs the way ODF renders them
+fn push_collapsed(paragraph: &mut String, text: &str) {
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !paragraph.ends_with(' ') && !paragraph.is_empty() {
+                paragraph.push(' ');
+            }
+        } else {
+            paragraph.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content() {
+        let content = r#"<office:document-content><office:body><office:text>
+            <text:tracked-changes><text:changed-region><text:deletion><text:p>gone</text:p></text:deletion></text:changed-region></text:tracked-changes>
+            <text:h text:outline-level="2">Scope</text:h>
+            <text:p>Hel<text:span>lo</text:span>   world<text:note><text:note-citation>1</text:note-citation><text:note-body><text:p>A note.</text:p></text:note-body></text:note></text:p>
+            <text:list text:style-name="L1">
+              <text:list-item><text:p>First</text:p>
+                <text:list><text:list-item><text:p>Nested</text:p></text:list-item></text:list>
+              </text:list-item>
+              <text:list-item><text:p>Second</text:p></text:list-item>
+            </text:list>
+            <table:table>
+              <table:table-row><table:table-cell><text:p>Name</text:p></table:table-cell><table:table-cell><text:p>Qty</text:p></table:table-cell></table:table-row>
+              <table:table-row><table:table-cell table:number-columns-repeated="2"><text:p>x</text:p></table:table-cell><table:table-cell table:number-columns-repeated="5"/></table:table-row>
+            </table:table>
+        </office:text></office:body></office:document-content>"#;
+        let styles = HashMap::from([("L1".to_string(), vec![true, false])]);
+
+        let text = parse_content(content, &styles).unwrap();
+        assert_eq!(text, vec![
+            "## Scope\nHello world[1]\n1. First\n  - Nested\n2. Second\n| Name | Qty |\n| --- | --- |\n| x | x |\n\n[1] A note."
+        ]);
+    }
+
+    #[test]
+    fn test_presentation_pages() {
+        let content = r#"<office:presentation>
+            <draw:page draw:name="One"><draw:frame><draw:text-box><text:p>Title one</text:p></draw:text-box></draw:frame>
+              <presentation:notes><draw:frame><draw:text-box><text:p>Speaker</text:p></draw:text-box></draw:frame></presentation:notes></draw:page>
+            <draw:page draw:name="Two"/>
+            <draw:page draw:name="Three"><draw:frame><draw:text-box><text:p>Title three</text:p></draw:text-box></draw:frame></draw:page>
+        </office:presentation>"#;
+        assert_eq!(parse_content(content, &HashMap::new()).unwrap(), vec!["Title one", "", "Title three"]);
+    }
+}
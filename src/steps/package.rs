@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use zip::ZipArchive;
+use crate::types::ProcessError;
+
+/// A relationship from a part of an Office Open XML package to another part or an external target
+pub(crate) struct Relationship {
+    pub kind: String,
+    pub target: String,
+}
+
+/// The text of a part of a zipped XML package, or `None` when the package doesn't have it
+pub(crate) fn read_part(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<String>, ProcessError> {
+    let mut content = String::new();
+    match archive.by_name(name) {
+        Ok(mut part) => {
+            part.read_to_string(&mut content)?;
+            Ok(Some(content))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+pub(crate) fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+/// Resolve a relationship target relative to the part that declares it
+pub(crate) fn resolve_target(source: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut segments: Vec<&str> = source.split('/').collect();
+    segments.pop();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => (),
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Relationships declared by a part, keyed by id
+pub(crate) fn part_relationships(archive: &mut ZipArchive<File>, part: &str) -> Result<HashMap<String, Relationship>, ProcessError> {
+    let (directory, file) = part.rsplit_once('/').unwrap_or(("", part));
+    let rels_path = format!("{}/_rels/{}.rels", directory, file);
+    match read_part(archive, &rels_path)? {
+        Some(content) => parse_relationships(&content),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn parse_relationships(content: &str) -> Result<HashMap<String, Relationship>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut targets = HashMap::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.name().as_ref() == b"Relationship" => {
+                if let (Some(id), Some(target)) = (attribute(e, b"Id"), attribute(e, b"Target")) {
+                    let kind = attribute(e, b"Type").unwrap_or_default();
+                    targets.insert(id, Relationship { kind, target });
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_target() {
+        assert_eq!(resolve_target("xl/workbook.xml", "worksheets/sheet1.xml"), "xl/worksheets/sheet1.xml");
+        assert_eq!(resolve_target("xl/worksheets/sheet1.xml", "../comments1.xml"), "xl/comments1.xml");
+        assert_eq!(resolve_target("xl/workbook.xml", "/xl/worksheets/sheet2.xml"), "xl/worksheets/sheet2.xml");
+    }
+}
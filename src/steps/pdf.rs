@@ -4,32 +4,120 @@ use image::DynamicImage;
 use std::path::Path;
 use tempfile::tempdir;
 use leptess::LepTess;
-use mupdf::{Document as MuDocument, Matrix, Colorspace};
-use tracing::{debug, trace};
+use lopdf::Document as LoDocument;
+use regex::bytes::Regex as BytesRegex;
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashSet};
+use tracing::{debug, trace, warn};
 use rayon::prelude::*;
 use crate::types::{ProcessError, Strategy, Config};
+use crate::render::{PdfRenderer, MupdfRenderer, renderer_for};
 use crate::processor::{
     ProcessingStep,
     AsyncProcessor,
     format_extracted_data,
+    format_page_data,
     format_ocr_data,
     optimize_image,
     clean_text,
     select_pages_to_process,
-    is_meaningful_text
+    is_meaningful_text,
+    record_metadata,
+    record_error,
+    correct_orientation,
+    strip_repeated_lines
 };
 use crate::proto::processor::{Query, Attachment};
 
+lazy_static! {
+    static ref OBJECT_HEADER_RE: BytesRegex = BytesRegex::new(r"(?-u)(?:^|[\s])(\d+)[ \t\r\n]+(\d+)[ \t\r\n]+obj[^A-Za-z]").unwrap();
+    static ref ROOT_REF_RE: BytesRegex = BytesRegex::new(r"(?-u)/Root[ \t\r\n]*(\d+)[ \t\r\n]+(\d+)[ \t\r\n]+R").unwrap();
+}
+
+/// Backend that produced the extracted PDF text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextBackend {
+    Renderer(&'static str),
+    Lopdf,
+}
+
+impl TextBackend {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TextBackend::Renderer(name) => name,
+            TextBackend::Lopdf => "lopdf",
+        }
+    }
+}
+
+/// Text extracted from a document, page by page
+struct ExtractedPages {
+    /// Zero-based page number and cleaned text of each page with text
+    pages: Vec<(i32, String)>,
+    chapters: Vec<(i32, String)>,
+    backend: TextBackend,
+}
+
+impl ExtractedPages {
+    /// Strip repeated headers and footers and join the pages into one text,
+    /// returning the text and the lines that were removed
+    fn into_text(self, tag_pages: bool) -> (Option<String>, Vec<String>) {
+        let (page_nums, mut texts): (Vec<i32>, Vec<String>) = self.pages.into_iter().unzip();
+        let removed = strip_repeated_lines(&mut texts);
+
+        let mut text = String::new();
+        for (page_num, page_text) in page_nums.into_iter().zip(texts) {
+            let page_text = page_text.trim();
+            if page_text.is_empty() {
+                continue;
+            }
+            if tag_pages {
+                let chapter = chapter_for_page(&self.chapters, page_num);
+                text.push_str(&format_page_data(page_text, (page_num + 1) as u32, chapter));
+            } else {
+                text.push_str(page_text);
+            }
+            text.push('\n');
+        }
+
+        let text = text.trim().to_string();
+        if !text.is_empty() {
+            (Some(text), removed)
+        } else {
+            (None, removed)
+        }
+    }
+}
+
 pub struct PDFProcessor;
 
 #[async_trait]
 impl AsyncProcessor for PDFProcessor {
     async fn process(&self, query: &mut Query, config: &Config) -> Result<(), ProcessError> {
         debug!("Starting PDF processing for file: {}", query.file_path);
+
+        // Only PDFs can use the configured renderer and the lopdf fallback;
+        // other paged documents (XPS, EPUB, CBZ, ...) are opened with mupdf
+        // and tagged per page so chapter boundaries survive
+        let paged = query.strategy == Strategy::PagedDocument.to_string();
+        let renderer: Box<dyn PdfRenderer> = if paged {
+            Box::new(MupdfRenderer)
+        } else {
+            renderer_for(&config.pdf_renderer)
+        };
         
         // Try to extract text directly from PDF
-        let extracted_text = self.extract_text(Path::new(&query.file_path)).await?;
-        trace!("Text extraction completed: {}", extracted_text.is_some());
+        let extracted = self.extract_text(renderer.as_ref(), Path::new(&query.file_path), paged).await?;
+        let backend = extracted.backend;
+        // Pages whose text layer gave text aren't sideways scans, so need no orientation check
+        let text_pages: HashSet<i32> = extracted.pages.iter().map(|(page_num, _)| *page_num).collect();
+        record_metadata(query, "pdf_text_backend", backend.as_str());
+
+        let (extracted_text, removed_lines) = extracted.into_text(paged);
+        trace!("Text extraction completed: {} ({})", extracted_text.is_some(), backend.as_str());
+        if !removed_lines.is_empty() {
+            record_metadata(query, "repeated_lines_removed", removed_lines.join("\n"));
+        }
         
         let has_extracted_text = if let Some(text) = extracted_text {
             let cleaned_text = clean_text(&text);
@@ -46,29 +134,45 @@ impl AsyncProcessor for PDFProcessor {
             false
         };
         
-        // Convert PDF to images
-        debug!("Converting PDF to images");
-        let images = self.convert_to_images(Path::new(&query.file_path), config).await?;
+        // Convert PDF to images. When the renderer could not open the document
+        // the lopdf text is all we have, so a rendering failure is not fatal.
+        debug!("Converting PDF to images with {}", renderer.name());
+        let images = match self.convert_to_images(renderer.as_ref(), Path::new(&query.file_path), config).await {
+            Ok(images) => images,
+            Err(e) if backend == TextBackend::Lopdf => {
+                warn!("Skipping page rendering: {}", e);
+                record_error(query, format!("pdf rendering skipped: {}", e));
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
         trace!("Converted {} pages to images", images.len());
         
         // Process images in parallel
         debug!("Processing images");
         let results: Vec<_> = images.into_par_iter()
             .enumerate()
-            .map(|(page_num, img)| {
-                self.process_single_image(img, page_num, config, has_extracted_text)
+            .map(|(index, (page_num, img))| {
+                self.process_single_image(img, index, config, has_extracted_text, text_pages.contains(&page_num))
             })
             .collect::<Result<Vec<_>, ProcessError>>()?;
         
         // Combine results
         let mut ocr_parts = Vec::new();
         let mut new_attachments = Vec::new();
-        for (ocr_text, attachment) in results {
+        let mut rotated_pages = Vec::new();
+        for (ocr_text, attachment, rotation) in results {
             if let Some(text) = ocr_text {
                 ocr_parts.push(text);
             }
+            if rotation != 0 {
+                rotated_pages.push(format!("{}:{}", attachment.page, rotation));
+            }
             new_attachments.push(attachment);
         }
+        if !rotated_pages.is_empty() {
+            record_metadata(query, "pdf_rotated_pages", rotated_pages.join(","));
+        }
         
         // Add OCR results and attachments
         query.prompt_parts.extend(ocr_parts);
@@ -82,7 +186,7 @@ impl AsyncProcessor for PDFProcessor {
 
 impl ProcessingStep for PDFProcessor {
     fn required_for(&self) -> Vec<Strategy> {
-        vec![Strategy::PDF]
+        vec![Strategy::PDF, Strategy::PagedDocument]
     }
 
     fn name(&self) -> &'static str {
@@ -91,36 +195,93 @@ impl ProcessingStep for PDFProcessor {
 }
 
 impl PDFProcessor {
-    async fn extract_text(&self, path: &Path) -> Result<Option<String>, ProcessError> {
-        // Open PDF with mupdf
-        let doc = MuDocument::open(path.to_str().ok_or_else(|| ProcessError::ExtractionFailed("Invalid path".to_string()))?)
+    async fn extract_text(
+        &self,
+        renderer: &dyn PdfRenderer,
+        path: &Path,
+        paged: bool
+    ) -> Result<ExtractedPages, ProcessError> {
+        match self.extract_text_with_renderer(renderer, path, paged) {
+            Ok(extracted) => Ok(extracted),
+            Err(e) if paged => Err(e),
+            Err(e) => {
+                warn!("{} failed to extract text, falling back to lopdf: {}", renderer.name(), e);
+                self.extract_text_lopdf(path)
+            }
+        }
+    }
+
+    fn extract_text_with_renderer(
+        &self,
+        renderer: &dyn PdfRenderer,
+        path: &Path,
+        with_chapters: bool
+    ) -> Result<ExtractedPages, ProcessError> {
+        let doc = renderer.open(path)
             .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
         
-        let mut text = String::new();
         let total_pages = doc.page_count()
             .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+        let chapters = if with_chapters { doc.chapters() } else { Vec::new() };
         
         // Extract text from each page
+        let mut pages = Vec::new();
         for page_num in 0..total_pages {
-            if let Ok(page) = doc.load_page(page_num) {
-                if let Ok(_) = page.bounds() {
-                    if let Ok(page_text) = page.to_text() {
-                        let cleaned_text = clean_text(&page_text);
-                        if !cleaned_text.is_empty() {
-                            text.push_str(&cleaned_text);
-                            text.push('\n');
-                        }
-                    }
+            if let Ok(page_text) = doc.page_text(page_num) {
+                let cleaned_text = clean_text(&page_text);
+                if !cleaned_text.is_empty() {
+                    pages.push((page_num, cleaned_text));
                 }
             }
         }
         
-        let text = text.trim().to_string();
-        if !text.is_empty() {
-            Ok(Some(text))
-        } else {
-            Ok(None)
+        Ok(ExtractedPages {
+            pages,
+            chapters,
+            backend: TextBackend::Renderer(renderer.name()),
+        })
+    }
+
+    fn extract_text_lopdf(&self, path: &Path) -> Result<ExtractedPages, ProcessError> {
+        let bytes = std::fs::read(path)?;
+
+        // Load with lopdf, rebuilding the xref table if the original is broken
+        let mut doc = match LoDocument::load_mem(&bytes) {
+            Ok(doc) => doc,
+            Err(e) => {
+                debug!("lopdf failed to load document ({}), attempting xref repair", e);
+                let repaired = repair_xref(&bytes)
+                    .ok_or_else(|| ProcessError::ExtractionFailed(e.to_string()))?;
+                LoDocument::load_mem(&repaired)
+                    .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?
+            }
+        };
+
+        // Many PDFs are encrypted with an empty user password
+        if doc.is_encrypted() {
+            if let Err(e) = doc.decrypt("") {
+                return Err(ProcessError::ExtractionFailed(e.to_string()));
+            }
         }
+
+        let page_numbers: Vec<u32> = doc.get_pages().keys().copied().collect();
+
+        // Extract text from each page's content streams
+        let mut pages = Vec::new();
+        for page_num in page_numbers {
+            if let Ok(page_text) = doc.extract_text(&[page_num]) {
+                let cleaned_text = clean_text(&page_text);
+                if !cleaned_text.is_empty() {
+                    pages.push((page_num as i32 - 1, cleaned_text));
+                }
+            }
+        }
+
+        Ok(ExtractedPages {
+            pages,
+            chapters: Vec::new(),
+            backend: TextBackend::Lopdf,
+        })
     }
 
     fn process_single_image(
@@ -128,8 +289,16 @@ impl PDFProcessor {
         img: DynamicImage,
         page_num: usize,
         config: &Config,
-        has_extracted_text: bool
-    ) -> Result<(Option<String>, Attachment), ProcessError> {
+        has_extracted_text: bool,
+        has_page_text: bool
+    ) -> Result<(Option<String>, Attachment, u32), ProcessError> {
+        // Turn sideways scans upright before they are attached or OCR'd
+        let (img, rotation) = if has_page_text {
+            (img, 0)
+        } else {
+            correct_orientation(img)
+        };
+
         // Optimize image
         let (optimized, buffer) = optimize_image(&img, config.max_image_size_mb)?;
         
@@ -141,7 +310,7 @@ impl PDFProcessor {
         
         // Skip OCR if we already have meaningful text
         if has_extracted_text {
-            return Ok((None, attachment));
+            return Ok((None, attachment, rotation));
         }
         
         // Create temp dir for OCR
@@ -170,83 +339,27 @@ impl PDFProcessor {
             None
         };
         
-        Ok((ocr_text, attachment))
+        Ok((ocr_text, attachment, rotation))
     }
 
     async fn convert_to_images(
         &self,
+        renderer: &dyn PdfRenderer,
         path: &Path,
         config: &Config
-    ) -> Result<Vec<DynamicImage>, ProcessError> {
-        // Open PDF with mupdf
-        let doc = MuDocument::open(path.to_str().ok_or_else(|| ProcessError::ConversionFailed("Invalid path".to_string()))?)
-            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
-        
-        let total_pages = doc.page_count()
-            .map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
+    ) -> Result<Vec<(i32, DynamicImage)>, ProcessError> {
+        let doc = renderer.open(path)?;
+        let total_pages = doc.page_count()?;
         
         // Determine which pages to convert
         let pages_to_convert = select_pages_to_process(total_pages, config);
         
-        let mut images = Vec::new();
-        let colorspace = Colorspace::device_rgb();
-        
         // Convert selected pages to images sequentially
+        let mut images = Vec::new();
         for page_num in pages_to_convert {
-            if let Ok(page) = doc.load_page(page_num) {
-                // Create pixmap for rendering with reduced initial scale
-                let pixmap = page.to_pixmap(
-                    &Matrix::new_scale(1.5, 1.5),  // Back to 1.5 scale
-                    &colorspace,
-                    1.0,
-                    false
-                ).map_err(|e| ProcessError::ConversionFailed(e.to_string()))?;
-                
-                // Convert to DynamicImage
-                let samples = pixmap.samples();
-                let width = pixmap.width() as u32;
-                let height = pixmap.height() as u32;
-                let stride = pixmap.stride();
-                let n = pixmap.n();
-                
-                // Pre-calculate buffer size and create with exact capacity
-                let buffer_size = (width * height * 3) as usize;
-                let mut rgb_data = Vec::with_capacity(buffer_size);
-                unsafe { rgb_data.set_len(buffer_size); }
-                
-                // Process all pixels in a single pass
-                let has_alpha = n == 4;
-                let mut i = 0;
-                
-                for y in 0..height {
-                    let row_start = y as usize * stride as usize;
-                    for x in 0..width {
-                        let pixel_start = row_start + x as usize * n as usize;
-                        if pixel_start + (n as usize) - 1 >= samples.len() {
-                            rgb_data[i] = 255;
-                            rgb_data[i + 1] = 255;
-                            rgb_data[i + 2] = 255;
-                        } else {
-                            let alpha = if has_alpha {
-                                samples[pixel_start + 3] as f32 / 255.0
-                            } else {
-                                1.0
-                            };
-                            
-                            for j in 0..3 {
-                                let color = samples[pixel_start + j] as f32 * alpha + 255.0 * (1.0 - alpha);
-                                rgb_data[i + j] = color as u8;
-                            }
-                        }
-                        i += 3;
-                    }
-                }
-                
-                // Create the image
-                let dynamic_image = image::RgbImage::from_raw(width, height, rgb_data)
-                    .ok_or_else(|| ProcessError::ConversionFailed("Failed to create image".to_string()))?;
-                
-                images.push(DynamicImage::ImageRgb8(dynamic_image));
+            match doc.render_page(page_num, 1.5) {
+                Ok(image) => images.push((page_num, image)),
+                Err(e) => warn!("Failed to render page {}: {}", page_num + 1, e),
             }
         }
         
@@ -257,4 +370,134 @@ impl PDFProcessor {
         LepTess::new(None, lang)
             .map_err(|e| ProcessError::OCRFailed(e.to_string()))
     }
-} 
\ No newline at end of file
+}
+
+/// Find the title of the chapter containing a page
+fn chapter_for_page(chapters: &[(i32, String)], page_num: i32) -> Option<&str> {
+    chapters.iter()
+        .take_while(|(start, _)| *start <= page_num)
+        .last()
+        .map(|(_, title)| title.as_str())
+}
+
+/// Rebuild a damaged cross-reference table by scanning for object headers.
+///
+/// Appends a fresh xref section and trailer to the original bytes so that the
+/// last definition of each object wins, as with an incremental update.
+fn repair_xref(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut offsets: BTreeMap<u32, (usize, u16)> = BTreeMap::new();
+    for caps in OBJECT_HEADER_RE.captures_iter(bytes) {
+        let id_match = caps.get(1)?;
+        let id = std::str::from_utf8(id_match.as_bytes()).ok()?.parse::<u32>().ok();
+        let generation = std::str::from_utf8(caps.get(2)?.as_bytes()).ok()?.parse::<u16>().ok();
+        if let (Some(id), Some(generation)) = (id, generation) {
+            offsets.insert(id, (id_match.start(), generation));
+        }
+    }
+
+    let root = ROOT_REF_RE.captures_iter(bytes).last()?;
+    let root_id = std::str::from_utf8(root.get(1)?.as_bytes()).ok()?;
+    let root_generation = std::str::from_utf8(root.get(2)?.as_bytes()).ok()?;
+
+    let max_id = *offsets.keys().last()?;
+    let mut repaired = bytes.to_vec();
+
+    // lopdf looks for %%EOF in the last 512 bytes, so push the original
+    // trailer out of that window before appending the new one
+    repaired.push(b'\n');
+    repaired.resize(repaired.len() + 512, b' ');
+    let xref_start = repaired.len() + 1;
+
+    let mut xref = format!("\nxref\n0 {}\n0000000000 65535 f\r\n", max_id + 1);
+    for id in 1..=max_id {
+        match offsets.get(&id) {
+            Some((offset, generation)) => xref.push_str(&format!("{:010} {:05} n\r\n", offset, generation)),
+            None => xref.push_str("0000000000 65535 f\r\n"),
+        }
+    }
+    xref.push_str(&format!(
+        "trailer\n<< /Size {} /Root {} {} R >>\nstartxref\n{}\n%%EOF\n",
+        max_id + 1, root_id, root_generation, xref_start
+    ));
+
+    repaired.extend_from_slice(xref.as_bytes());
+    Some(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::PdfDocument;
+
+    /// Stands in for mupdf opening an EPUB or XPS file with a chapter outline
+    struct Book;
+
+    impl PdfRenderer for Book {
+        fn name(&self) -> &'static str {
+            "book"
+        }
+
+        fn open(&self, _path: &Path) -> Result<Box<dyn PdfDocument>, ProcessError> {
+            Ok(Box::new(Book))
+        }
+    }
+
+    impl PdfDocument for Book {
+        fn page_count(&self) -> Result<i32, ProcessError> {
+            Ok(3)
+        }
+
+        fn render_page(&self, _page_num: i32, _scale: f32) -> Result<DynamicImage, ProcessError> {
+            Ok(DynamicImage::new_rgb8(1, 1))
+        }
+
+        fn page_text(&self, page_num: i32) -> Result<String, ProcessError> {
+            Ok(["Call me Ishmael.", "", "The \"Whale\" surfaced."][page_num as usize].to_string())
+        }
+
+        fn chapters(&self) -> Vec<(i32, String)> {
+            vec![(0, "Loomings".to_string()), (2, "The \"Chase\"".to_string())]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paged_document_text() {
+        for extension in ["epub", "xps"] {
+            assert_eq!(Strategy::from_extension(extension), Strategy::PagedDocument);
+        }
+        assert!(PDFProcessor.required_for().contains(&Strategy::PagedDocument));
+
+        // Pages are tagged with their number and chapter; blank pages are left out
+        let extracted = PDFProcessor.extract_text(&Book, Path::new("book.epub"), true).await.unwrap();
+        assert_eq!(extracted.backend, TextBackend::Renderer("book"));
+        let (text, removed) = extracted.into_text(true);
+        assert!(removed.is_empty());
+        assert_eq!(text.unwrap(), concat!(
+            "<PAGE NUMBER=1 CHAPTER=\"Loomings\">Call me Ishmael.</PAGE>\n",
+            "<PAGE NUMBER=3 CHAPTER=\"The 'Chase'\">The \"Whale\" surfaced.</PAGE>",
+        ));
+    }
+
+    #[test]
+    fn test_repair_xref() {
+        // Valid objects with a bogus xref table and startxref offset
+        let pdf = b"%PDF-1.4\n\
+            1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+            2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n\
+            3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R \
+            /Resources << /Font << /F1 5 0 R >> >> >>\nendobj\n\
+            4 0 obj\n<< /Length 45 >>\nstream\nBT /F1 12 Tf 72 712 Td (Hello repaired) Tj ET\nendstream\nendobj\n\
+            5 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>\nendobj\n\
+            xref\n0 6\n0000000000 65535 f\r\n0000000999 00000 n\r\n\
+            trailer\n<< /Size 6 /Root 1 0 R >>\nstartxref\n99999\n%%EOF\n";
+
+        assert!(LoDocument::load_mem(pdf).is_err(), "Original should fail to load");
+
+        let repaired = repair_xref(pdf).expect("Should rebuild xref");
+        let doc = LoDocument::load_mem(&repaired).expect("Repaired document should load");
+        assert_eq!(doc.get_pages().len(), 1, "Should find one page");
+
+        let text = doc.extract_text(&[1]).unwrap();
+        assert!(text.contains("Hello repaired"), "Should extract page text");
+    }
+}
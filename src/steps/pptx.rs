@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use zip::ZipArchive;
+use crate::types::ProcessError;
+use super::chart::load_charts;
+use super::media::{EmbeddedImage, ImageRefs};
+use super::table::markdown_table;
+use super::package::{attribute, part_relationships, read_part, resolve_target};
+
+/// A slide's title, body as Markdown and speaker notes
+#[derive(Default)]
+pub(crate) struct Slide {
+    pub title: Option<String>,
+    pub text: String,
+    pub notes: String,
+}
+
+/// A presentation's slides in show order, with the pictures placed on them
+pub(crate) struct PptxText {
+    pub(crate) slides: Vec<Slide>,
+    pub(crate) images: Vec<EmbeddedImage>,
+}
+
+/// A `p:sp` being read, with its placeholder role
+#[derive(Default)]
+struct Shape {
+    placeholder: Option<String>,
+    lines: Vec<String>,
+}
+
+/// An `a:p` being read, with whether it shows a bullet and at what level
+#[derive(Default)]
+struct Paragraph {
+    text: String,
+    bullet: Option<bool>,
+    level: usize,
+}
+
+/// An `a:tc`, with whether it continues the cell to its left or above
+#[derive(Default)]
+struct Cell {
+    text: String,
+    merged_left: bool,
+    merged_up: bool,
+}
+
+/// Text of each slide of a presentation, in the order of its slide list
+pub(crate) fn read_pptx(path: &Path) -> Result<PptxText, ProcessError> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+
+    let mut images = ImageRefs::default();
+    let mut slides = Vec::new();
+    for (index, part) in slide_parts(&mut archive)?.into_iter().enumerate() {
+        let Some(content) = read_part(&mut archive, &part)? else {
+            continue;
+        };
+        let charts = load_charts(&mut archive, &part)?;
+        let mut slide = parse_slide(&content, &part, index + 1, false, &charts, &mut images)?;
+
+        let notes_part = part_relationships(&mut archive, &part)?
+            .into_values()
+            .find(|rel| rel.kind.ends_with("/notesSlide"))
+            .map(|rel| resolve_target(&part, &rel.target));
+        if let Some(notes_part) = notes_part {
+            if let Some(notes) = read_part(&mut archive, &notes_part)? {
+                slide.notes = parse_slide(&notes, &notes_part, index + 1, true, &HashMap::new(), &mut images)?.text;
+            }
+        }
+        slides.push(slide);
+    }
+
+    let images = images.load(&mut archive)?;
+    Ok(PptxText { slides, images })
+}
+
+/// Slide parts in show order from `sldIdLst`, or by file number when the list can't be read
+fn slide_parts(archive: &mut ZipArchive<File>) -> Result<Vec<String>, ProcessError> {
+    let presentation = "ppt/presentation.xml";
+    if let Some(content) = read_part(archive, presentation)? {
+        let relationships = part_relationships(archive, presentation)?;
+        let mut reader = Reader::from_str(&content);
+        let mut buf = Vec::new();
+        let mut parts = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.name().as_ref() == b"p:sldId" => {
+                    if let Some(rel) = attribute(e, b"r:id").and_then(|id| relationships.get(&id)) {
+                        parts.push(resolve_target(presentation, &rel.target));
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+                _ => (),
+            }
+            buf.clear();
+        }
+        if !parts.is_empty() {
+            return Ok(parts);
+        }
+    }
+
+    let mut parts: Vec<(usize, String)> = archive.file_names()
+        .filter_map(|name| {
+            let number = name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse().ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    parts.sort();
+    Ok(parts.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Walk a slide's shapes, groups, tables and charts, with a placeholder where each picture sits
+///
+/// Notes slides keep only their body placeholder, leaving out the slide image, number and
+/// header or footer fields.
+fn parse_slide(
+    content: &str,
+    part: &str,
+    number: usize,
+    notes: bool,
+    charts: &HashMap<String, String>,
+    images: &mut ImageRefs,
+) -> Result<Slide, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+
+    let mut slide = Slide::default();
+    let mut blocks: Vec<String> = Vec::new();
+    let mut shape: Option<Shape> = None;
+    let mut paragraph: Option<Paragraph> = None;
+    let mut table: Option<Vec<Vec<Cell>>> = None;
+    let mut in_text = false;
+    // Alt text of the shape being read
+    let mut description: Option<String> = None;
+
+    loop {
+        let event = reader.read_event_into(&mut buf);
+        let empty = matches!(event, Ok(Event::Empty(_)));
+        match event {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                b"p:sp" if !empty => shape = Some(Shape::default()),
+                b"p:ph" => {
+                    if let Some(shape) = shape.as_mut() {
+                        // A placeholder without a type holds body content
+                        shape.placeholder = Some(attribute(e, b"type").unwrap_or_else(|| "body".to_string()));
+                    }
+                }
+                b"p:cNvPr" => {
+                    description = attribute(e, b"descr")
+                        .filter(|d| !d.trim().is_empty())
+                        .map(|d| d.split_whitespace().collect::<Vec<_>>().join(" "));
+                }
+                b"a:blip" if !notes => {
+                    if let Some(id) = attribute(e, b"r:embed") {
+                        let label = images.add(part, &id, number, description.take());
+                        match shape.as_mut() {
+                            Some(shape) => shape.lines.push(label),
+                            None => blocks.push(label),
+                        }
+                    }
+                }
+                b"c:chart" => {
+                    if let Some(chart) = attribute(e, b"r:id").and_then(|id| charts.get(&id)) {
+                        blocks.push(chart.clone());
+                    }
+                }
+                b"a:tbl" if !empty => table = Some(Vec::new()),
+                b"a:tr" => {
+                    if let Some(rows) = table.as_mut() {
+                        rows.push(Vec::new());
+                    }
+                }
+                b"a:tc" => {
+                    if let Some(row) = table.as_mut().and_then(|rows| rows.last_mut()) {
+                        row.push(Cell {
+                            text: String::new(),
+                            merged_left: attribute(e, b"hMerge").is_some_and(|v| v == "1" || v == "true"),
+                            merged_up: attribute(e, b"vMerge").is_some_and(|v| v == "1" || v == "true"),
+                        });
+                    }
+                }
+                b"a:p" if !empty => paragraph = Some(Paragraph::default()),
+                b"a:pPr" => {
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.level = attribute(e, b"lvl").and_then(|l| l.parse().ok()).unwrap_or(0);
+                    }
+                }
+                b"a:buChar" | b"a:buAutoNum" | b"a:buBlip" => {
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.bullet = Some(true);
+                    }
+                }
+                b"a:buNone" => {
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.bullet = Some(false);
+                    }
+                }
+                b"a:t" if !empty => in_text = true,
+                b"a:br" => {
+                    if let Some(paragraph) = paragraph.as_mut() {
+                        paragraph.text.push('\n');
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Text(e)) if in_text => {
+                let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                if let Some(paragraph) = paragraph.as_mut() {
+                    paragraph.text.push_str(&text);
+                }
+            }
+            Ok(Event::End(ref e)) => match e.name().as_ref() {
+                b"a:t" => in_text = false,
+                b"a:p" => {
+                    let Some(paragraph) = paragraph.take() else {
+                        continue;
+                    };
+                    let text = paragraph.text.trim();
+                    if text.is_empty() {
+                        // Nothing to keep
+                    } else if let Some(cell) = table.as_mut().and_then(|rows| rows.last_mut()).and_then(|row| row.last_mut()) {
+                        if !cell.text.is_empty() {
+                            cell.text.push(' ');
+                        }
+                        cell.text.push_str(text);
+                    } else if let Some(shape) = shape.as_mut() {
+                        // Body placeholders show bullets unless a paragraph turns them off
+                        let body = matches!(shape.placeholder.as_deref(), Some("body") | Some("obj"));
+                        if paragraph.bullet.unwrap_or(body && !notes) {
+                            shape.lines.push(format!("{}- {}", "  ".repeat(paragraph.level), text));
+                        } else {
+                            shape.lines.push(text.to_string());
+                        }
+                    }
+                }
+                b"p:sp" => {
+                    let Some(shape) = shape.take() else {
+                        continue;
+                    };
+                    match shape.placeholder.as_deref() {
+                        Some("title") | Some("ctrTitle") if !notes && slide.title.is_none() => {
+                            slide.title = Some(shape.lines.join(" ").split_whitespace().collect::<Vec<_>>().join(" "))
+                                .filter(|title| !title.is_empty());
+                        }
+                        Some("body") if notes => blocks.extend(shape.lines),
+                        _ if notes => (),
+                        _ => blocks.extend(shape.lines),
+                    }
+                }
+                b"a:tbl" => {
+                    let Some(rows) = table.take() else {
+                        continue;
+                    };
+                    let rows = table_rows(rows);
+                    if !rows.is_empty() {
+                        blocks.push(markdown_table(&rows));
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    slide.text = blocks.join("\n");
+    Ok(slide)
+}
+
+/// Lay out table cells, repeating merged cells into the columns and rows they cover
+fn table_rows(table: Vec<Vec<Cell>>) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for row in table {
+        let mut cells: Vec<String> = Vec::new();
+        for cell in row {
+            let text = if cell.merged_left {
+                cells.last().cloned().unwrap_or_default()
+            } else if cell.merged_up {
+                rows.last().and_then(|above| above.get(cells.len())).cloned().unwrap_or_default()
+            } else {
+                cell.text
+            };
+            cells.push(text);
+        }
+        rows.push(cells);
+    }
+    rows.retain(|row| row.iter().any(|cell| !cell.is_empty()));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slide() {
+        let content = r#"<p:sld><p:cSld><p:spTree>
+            <p:sp><p:nvSpPr><p:cNvPr id="2" name="Title 1"/><p:cNvSpPr/><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr>
+              <p:txBody><a:p><a:r><a:t>Quarterly</a:t></a:r><a:r><a:t xml:space="preserve"> results</a:t></a:r></a:p></p:txBody></p:sp>
+            <p:grpSp><p:sp><p:nvSpPr><p:cNvPr id="3" name="Content 2"/><p:cNvSpPr/><p:nvPr><p:ph idx="1"/></p:nvPr></p:nvSpPr>
+              <p:txBody><a:p><a:r><a:t>Revenue up</a:t></a:r></a:p><a:p><a:pPr lvl="1"/><a:r><a:t>EMEA</a:t></a:r></a:p></p:txBody></p:sp></p:grpSp>
+            <p:graphicFrame><a:graphic><a:graphicData><a:tbl>
+              <a:tr><a:tc gridSpan="2"><a:txBody><a:p><a:r><a:t>Region</a:t></a:r></a:p></a:txBody></a:tc><a:tc hMerge="1"><a:txBody><a:p/></a:txBody></a:tc></a:tr>
+              <a:tr><a:tc><a:txBody><a:p><a:r><a:t>EMEA</a:t></a:r></a:p></a:txBody></a:tc><a:tc><a:txBody><a:p><a:r><a:t>12</a:t></a:r></a:p></a:txBody></a:tc></a:tr>
+            </a:tbl></a:graphicData></a:graphic></p:graphicFrame>
+            <p:pic><p:nvPicPr><p:cNvPr id="4" name="Picture 3" descr="Revenue chart"/></p:nvPicPr><p:blipFill><a:blip r:embed="rId2"/></p:blipFill></p:pic>
+        </p:spTree></p:cSld></p:sld>"#;
+        let mut images = ImageRefs::default();
+        let slide = parse_slide(content, "ppt/slides/slide1.xml", 1, false, &HashMap::new(), &mut images).unwrap();
+        assert_eq!(slide.title.as_deref(), Some("Quarterly results"));
+        assert_eq!(
+            slide.text,
+            "- Revenue up\n  - EMEA\n| Region | Region |\n| --- | --- |\n| EMEA | 12 |\n\n[Image 1: Revenue chart]"
+        );
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        let content = r#"<p:notes><p:cSld><p:spTree>
+            <p:sp><p:nvSpPr><p:cNvPr id="2" name="Slide Image 1"/><p:cNvSpPr/><p:nvPr><p:ph type="sldImg"/></p:nvPr></p:nvSpPr></p:sp>
+            <p:sp><p:nvSpPr><p:cNvPr id="3" name="Notes 2"/><p:cNvSpPr/><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr>
+              <p:txBody><a:p><a:r><a:t>Mention the EMEA growth.</a:t></a:r></a:p></p:txBody></p:sp>
+            <p:sp><p:nvSpPr><p:cNvPr id="4" name="Slide Number 3"/><p:cNvSpPr/><p:nvPr><p:ph type="sldNum" idx="5"/></p:nvPr></p:nvSpPr>
+              <p:txBody><a:p><a:fld type="slidenum"><a:t>1</a:t></a:fld></a:p></p:txBody></p:sp>
+        </p:spTree></p:cSld></p:notes>"#;
+        let mut images = ImageRefs::default();
+        let notes = parse_slide(content, "ppt/notesSlides/notesSlide1.xml", 1, true, &HashMap::new(), &mut images).unwrap();
+        assert_eq!(notes.text, "Mention the EMEA growth.");
+    }
+}
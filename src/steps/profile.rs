@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use calamine::DataType;
+use chrono::NaiveDate;
+use serde::Serialize;
+use super::table::{SheetTable, cell_text, column_name, is_blank};
+
+/// Stop tracking new distinct values past this many per column
+const MAX_TRACKED_VALUES: usize = 10_000;
+const TOP_VALUES: usize = 3;
+
+/// Summary statistics for one column
+#[derive(Debug, Serialize)]
+pub(crate) struct ColumnProfile {
+    pub column: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub nulls: usize,
+    pub distinct: usize,
+    /// Set when `distinct` is a lower bound
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub distinct_capped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earliest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<String>,
+    pub top_values: Vec<(String, usize)>,
+}
+
+#[derive(Default)]
+struct ColumnStats {
+    nulls: usize,
+    numbers: usize,
+    dates: usize,
+    bools: usize,
+    texts: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    earliest: Option<String>,
+    latest: Option<String>,
+    values: HashMap<String, usize>,
+    capped: bool,
+}
+
+/// Accumulates column statistics over every row of a sheet, not just the sampled ones
+#[derive(Default)]
+pub(crate) struct ColumnProfiler {
+    first_row: Option<Vec<DataType>>,
+    columns: Vec<ColumnStats>,
+}
+
+impl ColumnProfiler {
+    pub fn observe(&mut self, row: &[DataType]) {
+        if row.iter().all(is_blank) {
+            return;
+        }
+
+        // Held back until the table knows whether it is a header
+        if self.first_row.is_none() {
+            self.first_row = Some(row.to_vec());
+            return;
+        }
+
+        self.record(row);
+    }
+
+    fn record(&mut self, row: &[DataType]) {
+        if self.columns.len() < row.len() {
+            self.columns.resize_with(row.len(), ColumnStats::default);
+        }
+        for (stats, cell) in self.columns.iter_mut().zip(row) {
+            stats.observe(cell);
+        }
+    }
+
+    /// Profiles for the columns that made it into `table`, labelled by its header row
+    pub fn finish(mut self, table: &SheetTable) -> Vec<ColumnProfile> {
+        if !table.has_header {
+            if let Some(first_row) = self.first_row.take() {
+                self.record(&first_row);
+            }
+        }
+
+        let labels = table.labels();
+        let mut columns = self.columns.into_iter().skip(table.col_offset);
+        labels.into_iter()
+            .enumerate()
+            .map(|(index, name)| columns.next().unwrap_or_default().profile(column_name(index), name))
+            .collect()
+    }
+}
+
+impl ColumnStats {
+    fn observe(&mut self, cell: &DataType) {
+        if is_blank(cell) {
+            self.nulls += 1;
+            return;
+        }
+
+        match cell {
+            DataType::Int(i) => self.observe_number(*i as f64),
+            DataType::Float(f) => self.observe_number(*f),
+            DataType::Bool(_) => self.bools += 1,
+            DataType::DateTimeIso(date) => self.observe_date(date),
+            DataType::String(s) => match parse_number(s) {
+                Some(number) => self.observe_number(number),
+                None if is_iso_date(s) => self.observe_date(s),
+                None => self.texts += 1,
+            },
+            _ => self.texts += 1,
+        }
+
+        let value = cell_text(cell);
+        if let Some(count) = self.values.get_mut(&value) {
+            *count += 1;
+        } else if self.values.len() < MAX_TRACKED_VALUES {
+            self.values.insert(value, 1);
+        } else {
+            self.capped = true;
+        }
+    }
+
+    fn observe_number(&mut self, number: f64) {
+        self.numbers += 1;
+        self.sum += number;
+        self.min = Some(self.min.map_or(number, |min| min.min(number)));
+        self.max = Some(self.max.map_or(number, |max| max.max(number)));
+    }
+
+    fn observe_date(&mut self, date: &str) {
+        self.dates += 1;
+        if self.earliest.as_deref().is_none_or(|earliest| date < earliest) {
+            self.earliest = Some(date.to_string());
+        }
+        if self.latest.as_deref().is_none_or(|latest| date > latest) {
+            self.latest = Some(date.to_string());
+        }
+    }
+
+    fn profile(self, column: String, name: String) -> ColumnProfile {
+        let filled = self.numbers + self.dates + self.bools + self.texts;
+        let kind = match filled {
+            0 => "empty",
+            n if n == self.numbers => "number",
+            n if n == self.dates => "date",
+            n if n == self.bools => "bool",
+            n if n == self.texts => "text",
+            _ => "mixed",
+        };
+
+        let distinct = self.values.len();
+        let mut top_values: Vec<(String, usize)> = self.values.into_iter().filter(|(_, count)| *count > 1).collect();
+        top_values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_values.truncate(TOP_VALUES);
+
+        ColumnProfile {
+            column,
+            name,
+            kind,
+            nulls: self.nulls,
+            distinct,
+            distinct_capped: self.capped,
+            min: self.min,
+            max: self.max,
+            mean: (self.numbers > 0).then(|| self.sum / self.numbers as f64),
+            earliest: self.earliest,
+            latest: self.latest,
+            top_values,
+        }
+    }
+}
+
+impl ColumnProfile {
+    /// One line per column, e.g. `B "Revenue": number, 2 nulls, 95 distinct, min 0, max 812.5, mean 40.12`
+    pub fn summary_line(&self) -> String {
+        let distinct = if self.distinct_capped { format!("{}+", self.distinct) } else { self.distinct.to_string() };
+        let mut line = format!(
+            "{} \"{}\": {}, {} nulls, {} distinct",
+            self.column, self.name, self.kind, self.nulls, distinct
+        );
+        if let (Some(min), Some(max), Some(mean)) = (self.min, self.max, self.mean) {
+            line.push_str(&format!(", min {}, max {}, mean {:.2}", min, max, mean));
+        }
+        if let (Some(earliest), Some(latest)) = (&self.earliest, &self.latest) {
+            line.push_str(&format!(", {} to {}", earliest, latest));
+        }
+        if !self.top_values.is_empty() {
+            let top: Vec<String> = self.top_values.iter()
+                .map(|(value, count)| format!("{} ({})", value, count))
+                .collect();
+            line.push_str(&format!(", top: {}", top.join(", ")));
+        }
+        line
+    }
+}
+
+/// Parse plain or displayed numbers such as `1,234.5`, `$99.00` or `12.5%`
+fn parse_number(text: &str) -> Option<f64> {
+    let cleaned: String = text.trim()
+        .trim_end_matches('%')
+        .chars()
+        .filter(|c| !matches!(c, ',' | '$' | '€' | '£' | '¥' | '₹' | '₩'))
+        .collect();
+    cleaned.parse::<f64>().ok().filter(|number| number.is_finite())
+}
+
+fn is_iso_date(text: &str) -> bool {
+    text.get(..10).is_some_and(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> DataType {
+        DataType::String(value.to_string())
+    }
+
+    #[test]
+    fn test_profile_columns() {
+        let rows = vec![
+            vec![text("region"), text("revenue"), text("date")],
+            vec![text("East"), text("$1,000.00"), text("2023-02-01")],
+            vec![text("East"), DataType::Empty, text("2023-01-15")],
+            vec![text("West"), text("500"), text("2023-03-31")],
+        ];
+        let mut profiler = ColumnProfiler::default();
+        for row in &rows {
+            profiler.observe(row);
+        }
+        let table = SheetTable::new("Sheet1", rows, (4, 3));
+        let profiles = profiler.finish(&table);
+
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(profiles[0].summary_line(), "A \"region\": text, 0 nulls, 2 distinct, top: East (2)");
+        assert_eq!(profiles[1].summary_line(), "B \"revenue\": number, 1 nulls, 2 distinct, min 500, max 1000, mean 750.00");
+        assert_eq!(profiles[2].summary_line(), "C \"date\": date, 0 nulls, 3 distinct, 2023-01-15 to 2023-03-31");
+    }
+}
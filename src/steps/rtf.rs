@@ -0,0 +1,359 @@
+use encoding_rs::{Encoding, UTF_16LE, WINDOWS_1252};
+use super::table::markdown_table;
+
+/// Destinations whose contents are never document text
+const SKIPPED_DESTINATIONS: [&str; 34] = [
+    "fonttbl", "colortbl", "stylesheet", "info", "pict", "object", "objdata", "shppict", "nonshppict",
+    "fldinst", "listtable", "listoverridetable", "rsidtbl", "generator", "xmlnstbl", "themedata",
+    "colorschememapping", "latentstyles", "datastore", "filetbl", "revtbl", "pgdsctbl", "bkmkstart",
+    "bkmkend", "header", "headerl", "headerr", "headerf", "footer", "footerl", "footerr", "footerf",
+    "ftnsep", "ftnsepc",
+];
+
+/// Formatting state that `{` saves and `}` restores
+#[derive(Clone)]
+struct Group {
+    skip: bool,
+    /// Fallback characters that follow each `\uN`
+    unicode_skip: usize,
+    note: bool,
+}
+
+/// Streaming RTF reader that writes paragraphs, tables and footnotes as it goes
+struct RtfReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    encoding: &'static Encoding,
+    groups: Vec<Group>,
+    /// Fallback characters still to drop after a `\uN`
+    pending_skip: usize,
+    bytes: Vec<u8>,
+    units: Vec<u16>,
+    blocks: Vec<String>,
+    paragraph: String,
+    in_table: bool,
+    row: Vec<String>,
+    table: Vec<Vec<String>>,
+    note: Option<String>,
+    notes: Vec<String>,
+}
+
+/// Plain text of an RTF document, with tables as Markdown and footnotes listed at the end
+pub(crate) fn read_rtf(input: &[u8]) -> String {
+    let mut reader = RtfReader {
+        input,
+        pos: 0,
+        encoding: WINDOWS_1252,
+        groups: vec![Group { skip: false, unicode_skip: 1, note: false }],
+        pending_skip: 0,
+        bytes: Vec::new(),
+        units: Vec::new(),
+        blocks: Vec::new(),
+        paragraph: String::new(),
+        in_table: false,
+        row: Vec::new(),
+        table: Vec::new(),
+        note: None,
+        notes: Vec::new(),
+    };
+    reader.run();
+    reader.finish()
+}
+
+impl RtfReader<'_> {
+    fn run(&mut self) {
+        while let Some(&byte) = self.input.get(self.pos) {
+            self.pos += 1;
+            match byte {
+                b'{' => {
+                    self.flush();
+                    self.pending_skip = 0;
+                    let group = self.group().clone();
+                    self.groups.push(Group { note: false, ..group });
+                }
+                b'}' => {
+                    self.flush();
+                    self.pending_skip = 0;
+                    if self.groups.len() > 1 {
+                        let group = self.groups.pop();
+                        if group.is_some_and(|group| group.note) {
+                            self.end_note();
+                        }
+                    }
+                }
+                b'\\' => self.control(),
+                b'\r' | b'\n' => (),
+                _ if self.pending_skip > 0 => self.pending_skip -= 1,
+                byte => {
+                    if !self.group().skip {
+                        self.flush_units();
+                        self.bytes.push(byte);
+                    }
+                }
+            }
+        }
+        self.flush();
+    }
+
+    fn group(&self) -> &Group {
+        // The root group is never popped
+        &self.groups[self.groups.len() - 1]
+    }
+
+    fn group_mut(&mut self) -> &mut Group {
+        let last = self.groups.len() - 1;
+        &mut self.groups[last]
+    }
+
+    /// Read a control word or symbol after a backslash
+    fn control(&mut self) {
+        let Some(&next) = self.input.get(self.pos) else {
+            return;
+        };
+
+        if !next.is_ascii_alphabetic() {
+            self.pos += 1;
+            match next {
+                b'\'' => {
+                    let hex = self.input.get(self.pos..self.pos + 2)
+                        .and_then(|hex| std::str::from_utf8(hex).ok())
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    self.pos += 2;
+                    match hex {
+                        _ if self.pending_skip > 0 => self.pending_skip -= 1,
+                        Some(byte) if !self.group().skip => {
+                            self.flush_units();
+                            self.bytes.push(byte);
+                        }
+                        _ => (),
+                    }
+                }
+                // Ignorable destinations we don't otherwise know are skipped whole
+                b'*' => self.group_mut().skip = true,
+                b'\\' | b'{' | b'}' => self.text(&(next as char).to_string()),
+                b'~' => self.text(" "),
+                b'_' => self.text("-"),
+                b'\r' | b'\n' => self.word("par", None),
+                _ => (),
+            }
+            return;
+        }
+
+        let start = self.pos;
+        while self.input.get(self.pos).is_some_and(u8::is_ascii_alphabetic) {
+            self.pos += 1;
+        }
+        let word = String::from_utf8_lossy(&self.input[start..self.pos]).into_owned();
+
+        let digits_start = self.pos;
+        if self.input.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        while self.input.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        let param = std::str::from_utf8(&self.input[digits_start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse::<i32>().ok());
+
+        // A single space ends the control word and is not text
+        if self.input.get(self.pos) == Some(&b' ') {
+            self.pos += 1;
+        }
+
+        self.word(&word, param);
+    }
+
+    fn word(&mut self, word: &str, param: Option<i32>) {
+        // Binary data is skipped even inside skipped groups so its bytes aren't read as syntax
+        if word == "bin" {
+            self.pos += param.unwrap_or(0).max(0) as usize;
+            return;
+        }
+        if self.group().skip {
+            return;
+        }
+
+        match word {
+            w if SKIPPED_DESTINATIONS.contains(&w) => self.group_mut().skip = true,
+            "ansicpg" => {
+                if let Some(encoding) = param.and_then(codepage_encoding) {
+                    self.encoding = encoding;
+                }
+            }
+            "mac" => self.encoding = Encoding::for_label(b"macintosh").unwrap_or(WINDOWS_1252),
+            "uc" => self.group_mut().unicode_skip = param.unwrap_or(1).max(0) as usize,
+            "u" => {
+                if let Some(unit) = param {
+                    self.flush_bytes();
+                    // Values above 32767 are written as negative numbers
+                    self.units.push(unit as i16 as u16);
+                    self.pending_skip = self.group().unicode_skip;
+                }
+            }
+            "footnote" => self.start_note(),
+            "chftn" => (),
+            "par" | "sect" | "page" => {
+                if self.note.is_some() || self.in_table {
+                    self.text(" ");
+                } else {
+                    self.end_paragraph();
+                }
+            }
+            "line" => self.text(if self.in_table || self.note.is_some() { " " } else { "\n" }),
+            "tab" => self.text("\t"),
+            "intbl" => self.in_table = true,
+            "pard" => self.in_table = false,
+            "cell" => {
+                self.flush();
+                let cell = std::mem::take(&mut self.paragraph);
+                self.row.push(cell.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+            "row" => {
+                self.flush();
+                let row = std::mem::take(&mut self.row);
+                if !row.is_empty() {
+                    self.table.push(row);
+                }
+            }
+            // Same separators as table::flatten_table
+            "nestcell" => self.text(" | "),
+            "nestrow" => self.text("; "),
+            "emdash" => self.text("\u{2014}"),
+            "endash" => self.text("\u{2013}"),
+            "bullet" => self.text("\u{2022}"),
+            "lquote" => self.text("\u{2018}"),
+            "rquote" => self.text("\u{2019}"),
+            "ldblquote" => self.text("\u{201C}"),
+            "rdblquote" => self.text("\u{201D}"),
+            "emspace" | "enspace" | "qmspace" => self.text(" "),
+            _ => (),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.group().skip {
+            return;
+        }
+        self.flush();
+        self.target().push_str(text);
+    }
+
+    /// Where text currently goes: the open footnote or the current paragraph
+    fn target(&mut self) -> &mut String {
+        match self.note.as_mut() {
+            Some(note) => note,
+            None => &mut self.paragraph,
+        }
+    }
+
+    fn flush(&mut self) {
+        self.flush_bytes();
+        self.flush_units();
+    }
+
+    fn flush_bytes(&mut self) {
+        if self.bytes.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.bytes);
+        let (text, _) = self.encoding.decode_without_bom_handling(&bytes);
+        self.target().push_str(&text);
+    }
+
+    fn flush_units(&mut self) {
+        if self.units.is_empty() {
+            return;
+        }
+        let bytes: Vec<u8> = std::mem::take(&mut self.units).into_iter().flat_map(u16::to_le_bytes).collect();
+        let (text, _) = UTF_16LE.decode_without_bom_handling(&bytes);
+        self.target().push_str(&text);
+    }
+
+    fn start_note(&mut self) {
+        if self.note.is_some() {
+            return;
+        }
+        self.flush();
+        self.group_mut().note = true;
+        let reference = format!("[{}]", self.notes.len() + 1);
+        self.paragraph.push_str(&reference);
+        self.note = Some(String::new());
+    }
+
+    fn end_note(&mut self) {
+        if let Some(note) = self.note.take() {
+            let text = note.split_whitespace().collect::<Vec<_>>().join(" ");
+            self.notes.push(format!("[{}] {}", self.notes.len() + 1, text));
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        self.flush();
+        self.end_table();
+        let paragraph = std::mem::take(&mut self.paragraph);
+        if !paragraph.trim().is_empty() {
+            self.blocks.push(paragraph.trim_end().to_string());
+        }
+    }
+
+    fn end_table(&mut self) {
+        if !self.row.is_empty() {
+            let row = std::mem::take(&mut self.row);
+            self.table.push(row);
+        }
+        if !self.table.is_empty() {
+            let table = std::mem::take(&mut self.table);
+            self.blocks.push(markdown_table(&table));
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_note();
+        self.end_paragraph();
+        self.blocks.append(&mut self.notes);
+        self.blocks.join("\n")
+    }
+}
+
+/// The encoding for a Windows code page number from `\ansicpgN`
+fn codepage_encoding(codepage: i32) -> Option<&'static Encoding> {
+    let label = match codepage {
+        874 | 1250..=1258 => format!("windows-{}", codepage),
+        866 => "ibm866".to_string(),
+        932 => "shift_jis".to_string(),
+        936 => "gbk".to_string(),
+        949 => "euc-kr".to_string(),
+        950 => "big5".to_string(),
+        10000 => "macintosh".to_string(),
+        65001 => "utf-8".to_string(),
+        _ => return None,
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_rtf() {
+        let rtf = br#"{\rtf1\ansi\ansicpg1251{\fonttbl{\f0 Times;}}{\colortbl;\red0\green0\blue0;}{\*\generator Writer;}
+{\info{\title Secret}}\pard\plain \f0 Caf\'e9 \'cf\'f0\'e8 r\u233?sum\u233?\par
+Second{\footnote\pard {\chftn} Note text.} line\line next\par
+\trowd\cellx100\cellx200\pard\intbl Name\cell Qty\cell\row
+\trowd\pard\intbl Apple\cell 3\cell\row
+\pard After {\field{\*\fldinst HYPERLINK "x"}{\fldrslt link}}\par}"#;
+        assert_eq!(
+            read_rtf(rtf),
+            "Caf\u{439} \u{41f}\u{440}\u{438} r\u{e9}sum\u{e9}\nSecond[1] line\nnext\n| Name | Qty |\n| --- | --- |\n| Apple | 3 |\n\nAfter link\n[1] Note text."
+        );
+    }
+
+    #[test]
+    fn test_codepage_encoding() {
+        assert_eq!(codepage_encoding(1252), Some(WINDOWS_1252));
+        assert_eq!(codepage_encoding(932).map(Encoding::name), Some("Shift_JIS"));
+        assert_eq!(codepage_encoding(437), None);
+    }
+}
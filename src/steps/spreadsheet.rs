@@ -1,32 +1,145 @@
 use anyhow::Result;
-use calamine::{Reader, open_workbook, Xlsx, Range, DataType, XlsxError};
+use calamine::{Reader, open_workbook_auto, Sheets, Range, DataType, Sheet, SheetType, SheetVisible};
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 use std::io::BufReader;
 use std::fs::File;
 use async_trait::async_trait;
+use chrono::NaiveDate;
+use tracing::{debug, warn};
 use crate::proto::processor::Query;
 use crate::types::{ProcessError, Strategy, Config};
-use crate::processor::{ProcessingStep, AsyncProcessor, validate_sheet_range};
+use crate::processor::{ProcessingStep, AsyncProcessor, format_sheet_data, format_sheet_summary, format_table_data, format_sheet_annotations, record_error, record_metadata};
+use super::delimited::read_delimited;
+use super::table::{SheetTable, sample_rows, column_name};
+use super::profile::{ColumnProfile, ColumnProfiler};
+use super::xlsx::{CellRange, RowObserver, SheetCells, SheetDetails, XlsxPackage, parse_range_ref};
 
 pub struct SpreadsheetProcessor;
 
 #[async_trait]
 impl AsyncProcessor for SpreadsheetProcessor {
-    async fn process(&self, query: &mut Query, _config: &Config) -> Result<(), ProcessError> {
-        let file_path: &str = query.file_path.as_str();
-        if file_path.is_empty() {
+    async fn process(&self, query: &mut Query, config: &Config) -> Result<(), ProcessError> {
+        if query.file_path.is_empty() {
             return Err(ProcessError::ExtractionFailed("No file path provided".to_string()));
         }
+
+        let file_path = query.file_path.clone();
+        let path = Path::new(&file_path);
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        if extension == "csv" || extension == "tsv" {
+            return self.process_delimited(query, path, config);
+        }
         
-        let mut workbook: Xlsx<BufReader<File>> = open_workbook(Path::new(file_path))
-            .map_err(|e: XlsxError| ProcessError::ExtractionFailed(e.to_string()))?;
+        // Pick the xls, xlsx/xlsm, xlsb or ods reader from the file extension
+        let mut workbook: Sheets<BufReader<File>> = open_workbook_auto(path)
+            .map_err(|e: calamine::Error| ProcessError::ExtractionFailed(e.to_string()))?;
+        
+        // Number formats, the date system, merged cells and tables are only read from xlsx/xlsm packages
+        let mut package = match extension.as_str() {
+            "xlsx" | "xlsm" => XlsxPackage::open(path)
+                .map_err(|e| warn!("Failed to read workbook details: {}", e))
+                .ok(),
+            _ => None,
+        };
+
+        // calamine reads formulas from every format, but comments come from the xlsx package
+        if config.sheet_annotations && package.is_none() {
+            record_metadata(query, "sheet_annotations_unavailable", format!("cell comments unavailable for this format ({}), only formulas are listed", extension));
+        }
+
+        let named_ranges = named_ranges(workbook.defined_names());
+        if !named_ranges.is_empty() {
+            let names: serde_json::Map<String, serde_json::Value> = named_ranges.iter()
+                .map(|(name, sheet, range)| (name.clone(), serde_json::Value::from(range_label(sheet, *range))))
+                .collect();
+            record_metadata(query, "named_ranges", serde_json::Value::Object(names).to_string());
+        }
         
-        if let Some(Ok(range)) = workbook.worksheet_range("Sheet1") {
-            let text: String = self.process_sheet(&range)?;
-            if !text.is_empty() {
-                query.prompt_parts.push(text);
+        // Process every selected sheet in workbook order
+        let sheets = workbook.sheets_metadata().to_vec();
+        let mut dimensions = Vec::new();
+        let mut tables = serde_json::Map::new();
+        let mut profiles = Vec::new();
+        let mut formulas_skipped = serde_json::Map::new();
+        for sheet in sheets {
+            if !self.is_sheet_selected(&sheet, config) {
+                debug!("Skipping sheet: {}", sheet.name);
+                continue;
+            }
+
+            // Excel tables and multi-cell named ranges each get their own part
+            let mut regions = match package.as_mut().map(|package| package.tables(&sheet.name)) {
+                Some(Ok(regions)) => regions,
+                Some(Err(e)) => {
+                    warn!("Failed to read tables for sheet {}: {}", sheet.name, e);
+                    Vec::new()
+                }
+                None => Vec::new(),
+            };
+            regions.extend(named_ranges.iter()
+                .filter(|(_, range_sheet, _)| *range_sheet == sheet.name)
+                .map(|(name, _, range)| (name.clone(), *range)));
+
+            // Sheets with more rows than can be shown are streamed instead of loaded whole
+            let dimension = match package.as_mut().map(|package| package.dimension(&sheet.name)) {
+                Some(Ok(dimension)) => dimension,
+                Some(Err(e)) => {
+                    warn!("Failed to read dimension of sheet {}: {}", sheet.name, e);
+                    None
+                }
+                None => None,
+            };
+            let streamed = dimension.filter(|(start, end)| end.0 >= start.0 && end.1 >= start.1 && end.0 - start.0 >= config.max_rows);
+            let is_streamed = streamed.is_some() && package.is_some();
+            let output = match (streamed, package.as_mut()) {
+                (Some(bounds), Some(package)) => self.stream_sheet(query, package, &sheet.name, bounds, &regions, config).map(Some),
+                _ => self.load_sheet(query, &mut workbook, package.as_mut(), &sheet.name, &regions, config),
+            };
+
+            match output {
+                Ok(Some((total, profile))) => {
+                    dimensions.push((sheet.name.clone(), total));
+                    if let Some(profile) = profile {
+                        profiles.push((sheet.name.clone(), profile));
+                    }
+                    for (name, bounds) in &regions {
+                        tables.insert(name.clone(), serde_json::Value::from(range_label(&sheet.name, *bounds)));
+                    }
+                }
+                Ok(None) => {
+                    debug!("Sheet is empty: {}", sheet.name);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read sheet {}: {}", sheet.name, e);
+                    record_error(query, format!("sheet {}: {}", sheet.name, e));
+                    continue;
+                }
+            }
+
+            if config.sheet_annotations {
+                // calamine only reads formulas by loading the whole sheet, which streaming avoids
+                if is_streamed {
+                    formulas_skipped.insert(sheet.name.clone(), serde_json::Value::from("sheet too large to load, so it was streamed"));
+                }
+                let annotations = self.sheet_annotations(&mut workbook, package.as_mut(), &sheet.name, !is_streamed, config);
+                if !annotations.is_empty() {
+                    query.prompt_parts.push(format_sheet_annotations(&annotations, &sheet.name));
+                }
             }
         }
+        record_dimensions(query, &dimensions);
+        record_profiles(query, &profiles);
+        if !tables.is_empty() {
+            record_metadata(query, "sheet_tables", serde_json::Value::Object(tables).to_string());
+        }
+        if !formulas_skipped.is_empty() {
+            record_metadata(query, "sheet_formulas_skipped", serde_json::Value::Object(formulas_skipped).to_string());
+        }
         
         Ok(())
     }
@@ -43,35 +156,759 @@ impl ProcessingStep for SpreadsheetProcessor {
 }
 
 impl SpreadsheetProcessor {
-    fn process_sheet(&self, range: &Range<DataType>) -> Result<String, ProcessError> {
-        // Get start and end coordinates
-        let start: (u32, u32) = range.start().ok_or_else(|| ProcessError::ExtractionFailed("Failed to get range start".to_string()))?;
-        let end: (u32, u32) = range.end().ok_or_else(|| ProcessError::ExtractionFailed("Failed to get range end".to_string()))?;
-        
-        // Validate range
-        let (adjusted_start_row, adjusted_start_col, adjusted_end_row, adjusted_end_col) = 
-            validate_sheet_range(start.0, start.1, end.0, end.1);
-
-        let mut text = String::new();
-
-        for row in adjusted_start_row..=adjusted_end_row {
-            for col in adjusted_start_col..=adjusted_end_col {
-                let coords: (u32, u32) = (row, col);
-                if let Some(cell) = range.get_value(coords) {
-                    match cell {
-                        DataType::String(s) => text.push_str(&s),
-                        DataType::Float(f) => text.push_str(&f.to_string()),
-                        DataType::Int(i) => text.push_str(&i.to_string()),
-                        DataType::Bool(b) => text.push_str(&b.to_string()),
-                        DataType::DateTime(dt) => text.push_str(&dt.to_string()),
-                        _ => ()
+    /// Check a sheet against its type, visibility and the configured name patterns
+    fn is_sheet_selected(&self, sheet: &Sheet, config: &Config) -> bool {
+        if sheet.typ == SheetType::ChartSheet {
+            return false;
+        }
+
+        if config.skip_hidden_sheets && sheet.visible != SheetVisible::Visible {
+            return false;
+        }
+
+        if !config.sheet_include.is_empty()
+            && !config.sheet_include.iter().any(|pattern| matches_pattern(pattern, &sheet.name)) {
+            return false;
+        }
+
+        !config.sheet_exclude.iter().any(|pattern| matches_pattern(pattern, &sheet.name))
+    }
+
+    fn process_delimited(&self, query: &mut Query, path: &Path, config: &Config) -> Result<(), ProcessError> {
+        let name = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("data");
+        let mut delimited = read_delimited(path, name, config)?;
+
+        record_metadata(query, "csv_delimiter", (delimited.delimiter as char).escape_default().to_string());
+        record_metadata(query, "csv_encoding", delimited.encoding);
+        record_metadata(query, "csv_header_row", delimited.table.has_header.to_string());
+
+        record_dimensions(query, &[(delimited.table.name.clone(), delimited.table.total)]);
+
+        if let Some(profile) = delimited.profile.take() {
+            push_summary(query, &delimited.table, &profile);
+            record_profiles(query, &[(delimited.table.name.clone(), profile)]);
+        }
+
+        let text: String = delimited.table.render(config.table_format)?;
+        if !text.trim().is_empty() {
+            query.prompt_parts.push(format_sheet_data(&text, &delimited.table.name));
+        }
+
+        Ok(())
+    }
+
+    /// One line per formula or comment, in cell order, e.g. `C10: =SUM(C2:C9)`
+    fn sheet_annotations(
+        &self,
+        workbook: &mut Sheets<BufReader<File>>,
+        package: Option<&mut XlsxPackage>,
+        sheet: &str,
+        formulas: bool,
+        config: &Config,
+    ) -> String {
+        let mut annotations: Vec<((u32, u32), String)> = Vec::new();
+
+        let formula_range = if formulas { workbook.worksheet_formula(sheet) } else { None };
+        match formula_range {
+            Some(Ok(formulas)) => {
+                let (start_row, start_col) = formulas.start().unwrap_or((0, 0));
+                for (row, col, formula) in formulas.used_cells() {
+                    let position = (start_row + row as u32, start_col + col as u32);
+                    annotations.push((position, format!("={}", formula)));
+                }
+            }
+            Some(Err(e)) => warn!("Failed to read formulas for sheet {}: {}", sheet, e),
+            None => (),
+        }
+
+        if let Some(package) = package {
+            match package.comments(sheet) {
+                Ok(comments) => {
+                    for comment in comments {
+                        let note = match &comment.author {
+                            Some(author) => format!("[comment by {}] {}", author, comment.text),
+                            None => format!("[comment] {}", comment.text),
+                        };
+                        annotations.push((comment.position, note));
                     }
-                    text.push(' ');
                 }
+                Err(e) => warn!("Failed to read comments for sheet {}: {}", sheet, e),
+            }
+        }
+
+        // Stable sort keeps a cell's formula ahead of its comment
+        annotations.sort_by_key(|(position, _)| *position);
+        let total = annotations.len();
+        let limit = config.max_annotations as usize;
+        let mut lines: Vec<String> = annotations.into_iter()
+            .take(limit)
+            .map(|((row, col), note)| format!("{}{}: {}", column_name(col as usize), row + 1, note.replace('\n', " ")))
+            .collect();
+        if total > limit {
+            lines.push(format!("[truncated: showing {} of {} annotations]", limit, total));
+        }
+        lines.join("\n")
+    }
+
+    /// Load a whole sheet through calamine, returning its size and column profiles
+    fn load_sheet(
+        &self,
+        query: &mut Query,
+        workbook: &mut Sheets<BufReader<File>>,
+        package: Option<&mut XlsxPackage>,
+        sheet: &str,
+        regions: &[(String, CellRange)],
+        config: &Config,
+    ) -> Result<Option<SheetOutput>, ProcessError> {
+        let range = match workbook.worksheet_range(sheet) {
+            Some(Ok(range)) => range,
+            Some(Err(e)) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            None => return Ok(None),
+        };
+        let (Some(start), Some(end)) = (range.start(), range.end()) else {
+            return Ok(None);
+        };
+
+        let details = match package.map(|package| package.sheet_details(sheet)) {
+            Some(Ok(details)) => details,
+            Some(Err(e)) => {
+                warn!("Failed to read details for sheet {}: {}", sheet, e);
+                SheetDetails::default()
+            }
+            None => SheetDetails::default(),
+        };
+        let view = SheetView::new(sheet, &range, (start, end), &details);
+
+        // Profiles cover every visible row, not just the sampled ones
+        let mut profiler = config.sheet_summary.then(ColumnProfiler::default);
+        if let Some(profiler) = profiler.as_mut() {
+            let (cols, _) = visible_cols(view.bounds, &details, config);
+            for row in (start.0..=end.0).filter(|&row| is_visible_row(row, &details, config)) {
+                profiler.observe(&row_cells(&view, row, &cols));
             }
-            text.push('\n');
         }
 
-        Ok(text)
+        self.emit_sheet(query, &view, regions, profiler, config).map(Some)
     }
-} 
\ No newline at end of file
+
+    /// Stream a large xlsx sheet, holding only the rows that will be shown in memory
+    fn stream_sheet(
+        &self,
+        query: &mut Query,
+        package: &mut XlsxPackage,
+        sheet: &str,
+        bounds: CellRange,
+        regions: &[(String, CellRange)],
+        config: &Config,
+    ) -> Result<SheetOutput, ProcessError> {
+        let mut keep: BTreeSet<u32> = sampled_rows(bounds, config).collect();
+        for (_, region) in regions {
+            if let Some(region) = clip_range(*region, bounds) {
+                keep.extend(sampled_rows(region, config));
+            }
+        }
+        debug!("Streaming sheet {} keeping {} rows", sheet, keep.len());
+
+        // Profiles see every row as it streams past, so they skip the merged cell lookup
+        let mut profiler = config.sheet_summary.then(ColumnProfiler::default);
+        let mut profile_cols: Option<Vec<u32>> = None;
+        let mut observe = |row: u32, cells: &[(u32, DataType)], details: &SheetDetails| {
+            let Some(profiler) = profiler.as_mut() else {
+                return;
+            };
+            if !(bounds.0.0..=bounds.1.0).contains(&row) || !is_visible_row(row, details, config) {
+                return;
+            }
+            let cols = profile_cols.get_or_insert_with(|| visible_cols(bounds, details, config).0);
+            let values: Vec<DataType> = cols.iter()
+                .map(|&col| match cells.binary_search_by_key(&col, |(cell_col, _)| *cell_col) {
+                    Ok(index) => display_cell(&cells[index].1, (row, col), details),
+                    Err(_) => DataType::Empty,
+                })
+                .collect();
+            profiler.observe(&values);
+        };
+        let observer: Option<&mut RowObserver> = if config.sheet_summary { Some(&mut observe) } else { None };
+        let (details, cells) = package.stream_sheet(sheet, &keep, observer)?;
+
+        let view = SheetView::new(sheet, &cells, bounds, &details);
+        self.emit_sheet(query, &view, regions, profiler, config)
+    }
+
+    /// Push the summary, sheet and table parts of a sheet, returning its size and column profiles
+    fn emit_sheet<S: CellSource>(
+        &self,
+        query: &mut Query,
+        view: &SheetView<S>,
+        regions: &[(String, CellRange)],
+        profiler: Option<ColumnProfiler>,
+        config: &Config,
+    ) -> Result<SheetOutput, ProcessError> {
+        let table: SheetTable = self.range_to_table(view, view.name, view.bounds, config);
+        let profile = profiler.map(|profiler| profiler.finish(&table));
+        if let Some(profile) = &profile {
+            push_summary(query, &table, profile);
+        }
+        let text: String = table.render(config.table_format)?;
+        if !text.trim().is_empty() {
+            query.prompt_parts.push(format_sheet_data(&text, view.name));
+        }
+
+        for (name, bounds) in regions {
+            let Some(bounds) = clip_range(*bounds, view.bounds) else {
+                continue;
+            };
+            let region: SheetTable = self.range_to_table(view, name, bounds, config);
+            let text: String = region.render(config.table_format)?;
+            if !text.trim().is_empty() {
+                query.prompt_parts.push(format_table_data(&text, name, view.name));
+            }
+        }
+
+        Ok((table.total, profile))
+    }
+
+    /// Build a table from the cells within `bounds`, applying formats, merges and hidden rows and columns
+    fn range_to_table<S: CellSource>(&self, view: &SheetView<S>, name: &str, bounds: CellRange, config: &Config) -> SheetTable {
+        let (start, end) = bounds;
+
+        // Hidden rows and columns count towards neither the output nor the totals.
+        // Rows are sampled before hidden ones are dropped so streamed sheets know which rows to keep.
+        let total_rows = (start.0..=end.0).filter(|&row| is_visible_row(row, view.details, config)).count();
+        let (cols, total_cols) = visible_cols(bounds, view.details, config);
+        let cells = sampled_rows(bounds, config)
+            .filter(|&row| is_visible_row(row, view.details, config))
+            .map(|row| row_cells(view, row, &cols))
+            .collect();
+
+        SheetTable::new(name, cells, (total_rows, total_cols))
+    }
+}
+
+/// Size and column profiles of a processed sheet
+type SheetOutput = ((usize, usize), Option<Vec<ColumnProfile>>);
+
+/// Cell lookup by zero-based (row, col), over a loaded range or the rows kept while streaming
+trait CellSource {
+    fn cell(&self, position: (u32, u32)) -> Option<&DataType>;
+}
+
+impl CellSource for Range<DataType> {
+    fn cell(&self, position: (u32, u32)) -> Option<&DataType> {
+        self.get_value(position)
+    }
+}
+
+impl CellSource for SheetCells {
+    fn cell(&self, position: (u32, u32)) -> Option<&DataType> {
+        self.get(&position)
+    }
+}
+
+/// The cells of a sheet along with its used range and layout
+struct SheetView<'a, S> {
+    name: &'a str,
+    cells: &'a S,
+    bounds: CellRange,
+    details: &'a SheetDetails,
+    merges: MergeIndex,
+}
+
+impl<'a, S> SheetView<'a, S> {
+    fn new(name: &'a str, cells: &'a S, bounds: CellRange, details: &'a SheetDetails) -> Self {
+        let merges = MergeIndex::new(&details.merged, bounds);
+        SheetView { name, cells, bounds, details, merges }
+    }
+}
+
+/// Merged spans on one row, as (first col, last col, top-left cell of the merged area)
+type MergeSpans = Vec<(u32, u32, (u32, u32))>;
+
+/// Merged areas within a sheet's used range, by row
+struct MergeIndex(HashMap<u32, MergeSpans>);
+
+impl MergeIndex {
+    fn new(merged: &[CellRange], bounds: CellRange) -> Self {
+        let mut rows: HashMap<u32, MergeSpans> = HashMap::new();
+        for &(origin, last) in merged {
+            let Some(((first_row, first_col), (last_row, last_col))) = clip_range((origin, last), bounds) else {
+                continue;
+            };
+            for row in first_row..=last_row {
+                rows.entry(row).or_default().push((first_col, last_col, origin));
+            }
+        }
+        MergeIndex(rows)
+    }
+
+    /// The top-left cell of the merged area covering a cell, or the cell itself
+    fn source(&self, (row, col): (u32, u32)) -> (u32, u32) {
+        self.0.get(&row)
+            .and_then(|spans| spans.iter().find(|(first, last, _)| (*first..=*last).contains(&col)))
+            .map_or((row, col), |(_, _, origin)| *origin)
+    }
+}
+
+/// Displayed values of `cols` on `row`, with cells under a merge showing its top-left value
+fn row_cells<S: CellSource>(view: &SheetView<S>, row: u32, cols: &[u32]) -> Vec<DataType> {
+    cols.iter()
+        .map(|&col| {
+            let source = view.merges.source((row, col));
+            match view.cells.cell(source) {
+                Some(cell) => display_cell(cell, source, view.details),
+                None => DataType::Empty,
+            }
+        })
+        .collect()
+}
+
+/// Absolute indices of the rows in `bounds` picked by the configured row sampling
+fn sampled_rows(bounds: CellRange, config: &Config) -> impl Iterator<Item = u32> {
+    let ((start_row, _), (end_row, _)) = bounds;
+    sample_rows((end_row - start_row + 1) as usize, config.max_rows as usize, config.row_sampling)
+        .into_iter()
+        .map(move |index| start_row + index as u32)
+}
+
+fn is_visible_row(row: u32, details: &SheetDetails, config: &Config) -> bool {
+    !(config.skip_hidden_cells && details.hidden_rows.contains(&row))
+}
+
+/// Visible columns within `bounds` up to max_cols, along with how many are visible in total
+fn visible_cols(bounds: CellRange, details: &SheetDetails, config: &Config) -> (Vec<u32>, usize) {
+    let ((_, start_col), (_, end_col)) = bounds;
+    let mut cols: Vec<u32> = (start_col..=end_col)
+        .filter(|col| !(config.skip_hidden_cells && details.hidden_cols.contains(col)))
+        .collect();
+    let total = cols.len();
+    cols.truncate(config.max_cols.max(1) as usize);
+    (cols, total)
+}
+
+/// Defined names that refer to a block of cells, as (name, sheet, range)
+fn named_ranges(names: &[(String, String)]) -> Vec<(String, String, CellRange)> {
+    names.iter()
+        .filter(|(name, _)| !name.starts_with("_xlnm."))
+        .filter_map(|(name, formula)| {
+            // Multi-area references like `Sheet1!A1,Sheet1!C3` are skipped
+            if formula.contains(',') {
+                return None;
+            }
+            let (sheet, reference) = formula.trim_start_matches('=').rsplit_once('!')?;
+            let sheet = match sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                Some(quoted) => quoted.replace("''", "'"),
+                None => sheet.to_string(),
+            };
+            let range = parse_range_ref(reference)?;
+            (range.0 != range.1).then(|| (name.clone(), sheet, range))
+        })
+        .collect()
+}
+
+/// Intersection of two cell ranges, if they overlap
+fn clip_range(range: CellRange, within: CellRange) -> Option<CellRange> {
+    let start = (range.0.0.max(within.0.0), range.0.1.max(within.0.1));
+    let end = (range.1.0.min(within.1.0), range.1.1.min(within.1.1));
+    (start.0 <= end.0 && start.1 <= end.1).then_some((start, end))
+}
+
+/// A1-style label for a range on a sheet, e.g. `Sheet1!A1:C10`
+fn range_label(sheet: &str, ((start_row, start_col), (end_row, end_col)): CellRange) -> String {
+    format!(
+        "{}!{}{}:{}{}",
+        sheet,
+        column_name(start_col as usize), start_row + 1,
+        column_name(end_col as usize), end_row + 1
+    )
+}
+
+/// Convert date serials, durations and formatted numbers into the values Excel shows
+fn display_cell(cell: &DataType, position: (u32, u32), details: &SheetDetails) -> DataType {
+    match cell {
+        DataType::DateTime(serial) => excel_datetime(*serial)
+            .map(DataType::DateTimeIso)
+            .unwrap_or_else(|| cell.clone()),
+        DataType::Duration(days) => DataType::String(format_duration(*days)),
+        DataType::Float(value) => match details.formats.get(&position) {
+            Some(format) => DataType::String(format.apply(*value)),
+            None => cell.clone(),
+        },
+        DataType::Int(value) => match details.formats.get(&position) {
+            Some(format) => DataType::String(format.apply(*value as f64)),
+            None => cell.clone(),
+        },
+        _ => cell.clone(),
+    }
+}
+
+/// ISO-8601 date, time or date-time for an Excel serial in the 1900 date system
+///
+/// Both calamine and the streaming xlsx reader move 1904 workbook serials to this system.
+fn excel_datetime(serial: f64) -> Option<String> {
+    if !serial.is_finite() || serial < 0.0 {
+        return None;
+    }
+
+    // The 1900 system counts a nonexistent 1900-02-29, so later serials start a day earlier
+    let epoch = if serial < 60.0 {
+        NaiveDate::from_ymd_opt(1899, 12, 31)?
+    } else {
+        NaiveDate::from_ymd_opt(1899, 12, 30)?
+    };
+
+    let seconds = (serial * 86400.0).round() as i64;
+    let datetime = epoch.and_hms_opt(0, 0, 0)?.checked_add_signed(chrono::Duration::seconds(seconds))?;
+
+    if seconds < 86400 {
+        Some(datetime.format("%H:%M:%S").to_string())
+    } else if seconds % 86400 == 0 {
+        Some(datetime.format("%Y-%m-%d").to_string())
+    } else {
+        Some(datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+}
+
+/// Elapsed time as hours:minutes:seconds, with hours allowed past 24
+fn format_duration(days: f64) -> String {
+    let seconds = (days * 86400.0).round() as i64;
+    let sign = if seconds < 0 { "-" } else { "" };
+    let seconds = seconds.abs();
+    format!("{}{}:{:02}:{:02}", sign, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Add the column profile part for a sheet
+fn push_summary(query: &mut Query, table: &SheetTable, profile: &[ColumnProfile]) {
+    let mut lines = vec![format!("rows: {}, columns: {}", table.total.0, table.total.1)];
+    lines.extend(profile.iter().map(ColumnProfile::summary_line));
+    query.prompt_parts.push(format_sheet_summary(&lines.join("\n"), &table.name));
+}
+
+/// Record column profiles as a JSON object keyed by sheet name
+fn record_profiles(query: &mut Query, profiles: &[(String, Vec<ColumnProfile>)]) {
+    if profiles.is_empty() {
+        return;
+    }
+    let profiles: serde_json::Map<String, serde_json::Value> = profiles.iter()
+        .filter_map(|(name, profile)| serde_json::to_value(profile).ok().map(|value| (name.clone(), value)))
+        .collect();
+    record_metadata(query, "sheet_profiles", serde_json::Value::Object(profiles).to_string());
+}
+
+/// Record each sheet's full size as a JSON object keyed by sheet name
+fn record_dimensions(query: &mut Query, tables: &[(String, (usize, usize))]) {
+    if tables.is_empty() {
+        return;
+    }
+    let dimensions: serde_json::Map<String, serde_json::Value> = tables.iter()
+        .map(|(name, (rows, cols))| (name.clone(), serde_json::json!({ "rows": rows, "cols": cols })))
+        .collect();
+    record_metadata(query, "sheet_dimensions", serde_json::Value::Object(dimensions).to_string());
+}
+
+/// Case-insensitive wildcard match supporting `*` and `?`
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("Sheet1", "sheet1"));
+        assert!(matches_pattern("Q*", "Q1 2024"));
+        assert!(matches_pattern("*data*", "Raw Data Export"));
+        assert!(matches_pattern("Q?", "Q3"));
+        assert!(!matches_pattern("Q?", "Q10"));
+        assert!(!matches_pattern("Summary", "Summary 2"));
+    }
+
+    #[test]
+    fn test_named_ranges() {
+        let names = vec![
+            ("Sales".to_string(), "'Q1 ''24'!$A$1:$C$10".to_string()),
+            ("Rate".to_string(), "Inputs!$B$2".to_string()),
+            ("_xlnm.Print_Area".to_string(), "Sheet1!$A$1:$Z$99".to_string()),
+        ];
+        assert_eq!(named_ranges(&names), vec![("Sales".to_string(), "Q1 '24".to_string(), ((0, 0), (9, 2)))]);
+        assert_eq!(range_label("Q1", ((0, 0), (9, 2))), "Q1!A1:C10");
+    }
+
+    #[test]
+    fn test_merge_index() {
+        let merges = MergeIndex::new(&[((1, 1), (3, 2)), ((0, 4), (0, 9))], ((0, 0), (5, 5)));
+        assert_eq!(merges.source((2, 2)), (1, 1));
+        assert_eq!(merges.source((3, 1)), (1, 1));
+        assert_eq!(merges.source((0, 5)), (0, 4));
+        assert_eq!(merges.source((4, 1)), (4, 1));
+        assert_eq!(merges.source((0, 3)), (0, 3));
+    }
+
+    #[test]
+    fn test_excel_datetime() {
+        assert_eq!(excel_datetime(1.0).as_deref(), Some("1900-01-01"));
+        assert_eq!(excel_datetime(45123.5).as_deref(), Some("2023-07-16T12:00:00"));
+        assert_eq!(excel_datetime(0.75).as_deref(), Some("18:00:00"));
+        assert_eq!(format_duration(1.5), "36:00:00");
+    }
+
+    /// Write a one-sheet xlsx with the given `workbookPr` attributes and sheet data
+    fn write_workbook(path: &Path, workbook_pr: &str, sheet_data: &str) {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let workbook = format!(
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><workbookPr {}/><sheets><sheet name="Data" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            workbook_pr
+        );
+        let rows = sheet_data.matches("<row ").count();
+        let sheet = format!(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><dimension ref="A1:B{}"/><sheetData>{}</sheetData></worksheet>"#,
+            rows, sheet_data
+        );
+        let parts = [
+            ("xl/workbook.xml", workbook.as_str()),
+            ("xl/_rels/workbook.xml.rels", r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#),
+            ("xl/styles.xml", r#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><cellXfs count="2"><xf numFmtId="0"/><xf numFmtId="14" applyNumberFormat="1"/></cellXfs></styleSheet>"#),
+            ("xl/worksheets/sheet1.xml", sheet.as_str()),
+        ];
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in parts {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn write_ods(path: &Path, rows: &str) {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let content = format!(
+            r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"><office:body><office:spreadsheet><table:table table:name="Data">{}</table:table></office:spreadsheet></office:body></office:document-content>"#,
+            rows
+        );
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in [("mimetype", "application/vnd.oasis.opendocument.spreadsheet"), ("content.xml", content.as_str())] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// An xls workbook with one sheet, `Data`, holding `Item | Qty` over `a | 1`
+    fn write_xls(path: &Path) {
+        use std::io::Write;
+
+        fn record(kind: u16, body: &[u8]) -> Vec<u8> {
+            [kind.to_le_bytes().as_slice(), &(body.len() as u16).to_le_bytes(), body].concat()
+        }
+        let label = |row: u16, col: u16, text: &str| {
+            let cell = [row.to_le_bytes(), col.to_le_bytes(), 0u16.to_le_bytes(), (text.len() as u16).to_le_bytes()].concat();
+            record(0x0204, &[cell.as_slice(), &[0], text.as_bytes()].concat())
+        };
+        let number = |row: u16, col: u16, value: f64| {
+            let cell = [row.to_le_bytes(), col.to_le_bytes(), 0u16.to_le_bytes()].concat();
+            record(0x0203, &[cell.as_slice(), &value.to_le_bytes()].concat())
+        };
+
+        // The workbook globals, a BoundSheet8 and EOF, are followed by the sheet's records
+        let name = b"Data";
+        let sheet_offset = (4 + 8 + name.len()) + 4;
+        let bound_sheet = record(0x0085, &[(sheet_offset as u32).to_le_bytes().as_slice(), &[0, 0, name.len() as u8, 0], name].concat());
+        let stream = [
+            bound_sheet, record(0x000A, &[]),
+            label(0, 0, "Item"), label(0, 1, "Qty"), label(1, 0, "a"), number(1, 1, 1.0), record(0x000A, &[]),
+        ].concat();
+
+        let mut file = cfb::create(path).unwrap();
+        file.create_stream("/Workbook").unwrap().write_all(&stream).unwrap();
+        file.flush().unwrap();
+    }
+
+    /// An xlsb workbook with the same sheet as `write_xls`
+    fn write_xlsb(path: &Path) {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        fn record(kind: u16, body: &[u8]) -> Vec<u8> {
+            let mut data = if kind < 0x80 { vec![kind as u8] } else { vec![(kind & 0x7F) as u8 | 0x80, (kind >> 7) as u8] };
+            let mut len = body.len();
+            loop {
+                let byte = (len & 0x7F) as u8;
+                len >>= 7;
+                data.push(if len > 0 { byte | 0x80 } else { byte });
+                if len == 0 {
+                    break;
+                }
+            }
+            data.extend(body);
+            data
+        }
+        let wide = |text: &str| {
+            let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            [(text.len() as u32).to_le_bytes().as_slice(), &units].concat()
+        };
+        let cell = |col: u32, value: &[u8]| [col.to_le_bytes().as_slice(), &0u32.to_le_bytes(), value].concat();
+
+        let bundle = [0u32.to_le_bytes().as_slice(), &1u32.to_le_bytes(), &wide("rId1"), &wide("Data")].concat();
+        let workbook = [record(0x009C, &bundle), record(0x0090, &[]), record(0x009D, &[])].concat();
+        let dimension: Vec<u8> = [0u32, 1, 0, 1].iter().flat_map(|n| n.to_le_bytes()).collect();
+        let sheet = [
+            record(0x0094, &dimension), record(0x0091, &[]),
+            record(0x0000, &0u32.to_le_bytes()), record(0x0006, &cell(0, &wide("Item"))), record(0x0006, &cell(1, &wide("Qty"))),
+            record(0x0000, &1u32.to_le_bytes()), record(0x0006, &cell(0, &wide("a"))), record(0x0005, &cell(1, &1f64.to_le_bytes())),
+            record(0x0092, &[]),
+        ].concat();
+
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let parts: [(&str, &[u8]); 3] = [
+            ("xl/workbook.bin", &workbook),
+            ("xl/_rels/workbook.bin.rels", br#"<Relationships><Relationship Id="rId1" Target="worksheets/sheet1.bin"/></Relationships>"#),
+            ("xl/worksheets/sheet1.bin", &sheet),
+        ];
+        for (name, content) in parts {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn query(path: &Path) -> Query {
+        Query {
+            file_type: "xlsx".to_string(),
+            file_path: path.to_string_lossy().into_owned(),
+            strategy: Strategy::Spreadsheet.to_string(),
+            prompt_parts: Vec::new(),
+            attachments: Vec::new(),
+            system: String::new(),
+            prompt: String::new(),
+            metadata: Some(Default::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_1904_dates_loaded_and_streamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dates.xlsx");
+        write_workbook(&path, r#"date1904="1""#, concat!(
+            r#"<row r="1"><c r="A1" t="inlineStr"><is><t>Date</t></is></c></row>"#,
+            r#"<row r="2"><c r="A2" s="1"><v>43661</v></c></row>"#,
+            r#"<row r="3"><c r="A3" s="1"><v>43662</v></c></row>"#,
+            r#"<row r="4"><c r="A4" s="1"><v>43663</v></c></row>"#,
+        ));
+
+        // Four rows fit when loaded through calamine; two rows force the streaming reader
+        for max_rows in [100, 2] {
+            let mut query = query(&path);
+            let config = Config { max_rows, ..Config::default() };
+            SpreadsheetProcessor.process(&mut query, &config).await.unwrap();
+
+            assert!(query.prompt_parts[0].contains("| 2023-07-16 |"), "max_rows {}: {}", max_rows, query.prompt_parts[0]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_sheet_totals_and_annotations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.xlsx");
+        write_workbook(&path, "", concat!(
+            r#"<row r="1"><c r="A1" t="inlineStr"><is><t>Item</t></is></c><c r="B1" t="inlineStr"><is><t>Qty</t></is></c></row>"#,
+            r#"<row r="2"><c r="A2" t="inlineStr"><is><t>a</t></is></c><c r="B2"><v>1</v></c></row>"#,
+            r#"<row r="3"><c r="A3" t="inlineStr"><is><t>b</t></is></c><c r="B3"><v>2</v></c></row>"#,
+            r#"<row r="4" hidden="1"><c r="A4" t="inlineStr"><is><t>c</t></is></c><c r="B4"><v>3</v></c></row>"#,
+            r#"<row r="5"><c r="A5" t="inlineStr"><is><t>Total</t></is></c><c r="B5"><f>SUM(B2:B4)</f><v>6</v></c></row>"#,
+        ));
+
+        let mut query = query(&path);
+        let config = Config { max_rows: 2, sheet_annotations: true, ..Config::default() };
+        SpreadsheetProcessor.process(&mut query, &config).await.unwrap();
+
+        // Head sampling stops reading cells after row 2, but the hidden row 4 is still left out of the total
+        let properties = &query.metadata.unwrap().properties;
+        assert_eq!(properties["sheet_dimensions"], r#"{"Data":{"cols":2,"rows":4}}"#);
+        assert!(properties.contains_key("sheet_formulas_skipped"));
+        assert_eq!(query.prompt_parts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_annotation_limit_and_availability() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("formulas.xlsx");
+        write_workbook(&path, "", concat!(
+            r#"<row r="1"><c r="A1"><v>1</v></c><c r="B1"><f>A1*2</f><v>2</v></c></row>"#,
+            r#"<row r="2"><c r="A2"><v>2</v></c><c r="B2"><f>A2*2</f><v>4</v></c></row>"#,
+            r#"<row r="3"><c r="A3"><v>3</v></c><c r="B3"><f>A3*2</f><v>6</v></c></row>"#,
+        ));
+
+        // The annotation limit is separate from the row limit
+        let mut xlsx = query(&path);
+        let config = Config { max_annotations: 2, sheet_annotations: true, ..Config::default() };
+        SpreadsheetProcessor.process(&mut xlsx, &config).await.unwrap();
+        assert_eq!(
+            xlsx.prompt_parts[1],
+            "<SHEET_ANNOTATIONS NAME=\"Data\">B1: =A1*2\nB2: =A2*2\n[truncated: showing 2 of 3 annotations]</SHEET_ANNOTATIONS>"
+        );
+        assert!(!xlsx.metadata.unwrap().properties.contains_key("sheet_annotations_unavailable"));
+
+        // Other formats only have their formulas listed, and say so
+        let path = dir.path().join("formulas.ods");
+        write_ods(&path, concat!(
+            r#"<table:table-row><table:table-cell office:value-type="float" office:value="1"><text:p>1</text:p></table:table-cell>"#,
+            r#"<table:table-cell table:formula="of:=[.A1]*2" office:value-type="float" office:value="2"><text:p>2</text:p></table:table-cell></table:table-row>"#,
+        ));
+        let mut ods = Query { file_type: "ods".to_string(), ..query(&path) };
+        SpreadsheetProcessor.process(&mut ods, &config).await.unwrap();
+        assert!(ods.prompt_parts[1].contains("B1: =of:=[.A1]*2"), "{}", ods.prompt_parts[1]);
+        assert!(ods.metadata.unwrap().properties["sheet_annotations_unavailable"].contains("(ods)"));
+    }
+
+    #[tokio::test]
+    async fn test_each_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows = concat!(
+            r#"<row r="1"><c r="A1" t="inlineStr"><is><t>Item</t></is></c><c r="B1" t="inlineStr"><is><t>Qty</t></is></c></row>"#,
+            r#"<row r="2"><c r="A2" t="inlineStr"><is><t>a</t></is></c><c r="B2"><v>1</v></c></row>"#,
+        );
+        write_workbook(&dir.path().join("data.xlsm"), "", rows);
+        write_xls(&dir.path().join("data.xls"));
+        write_xlsb(&dir.path().join("data.xlsb"));
+        write_ods(&dir.path().join("data.ods"), concat!(
+            r#"<table:table-row><table:table-cell office:value-type="string"><text:p>Item</text:p></table:table-cell><table:table-cell office:value-type="string"><text:p>Qty</text:p></table:table-cell></table:table-row>"#,
+            r#"<table:table-row><table:table-cell office:value-type="string"><text:p>a</text:p></table:table-cell><table:table-cell office:value-type="float" office:value="1"><text:p>1</text:p></table:table-cell></table:table-row>"#,
+        ));
+        std::fs::write(dir.path().join("data.tsv"), "Item\tQty\na\t1\n").unwrap();
+
+        for extension in ["xlsm", "xls", "xlsb", "ods", "tsv"] {
+            let path = dir.path().join(format!("data.{}", extension));
+            let mut query = Query { file_type: extension.to_string(), ..query(&path) };
+            SpreadsheetProcessor.process(&mut query, &Config::default()).await.unwrap();
+            let text = query.prompt_parts.concat();
+            assert!(text.contains("| Item | Qty |\n| --- | --- |\n| a | 1 |"), "{}: {}", extension, text);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashSet;
+use calamine::DataType;
+use serde_json::Value;
+use crate::types::{ProcessError, RowSampling, TableFormat};
+
+const HEADER_SNIFF_ROWS: usize = 50;
+
+/// A sheet's cells as rows, shared by the workbook and delimited text readers
+pub(crate) struct SheetTable {
+    pub name: String,
+    pub rows: Vec<Vec<DataType>>,
+    pub has_header: bool,
+    /// Rows and columns kept after sampling, before trimming
+    pub shown: (usize, usize),
+    /// Rows and columns in the whole sheet
+    pub total: (usize, usize),
+    /// Empty leading columns trimmed from the rows given to `new`
+    pub col_offset: usize,
+}
+
+impl SheetTable {
+    /// Build a table from sampled rows, trimming empty borders and detecting the header row
+    pub fn new(name: &str, rows: Vec<Vec<DataType>>, total: (usize, usize)) -> Self {
+        let shown = (rows.len(), rows.iter().map(Vec::len).max().unwrap_or(0));
+        let (rows, col_offset) = trim_empty(rows);
+        let has_header = detect_header(&rows);
+        SheetTable { name: name.to_string(), rows, has_header, shown, total, col_offset }
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows.first().map_or(0, Vec::len)
+    }
+
+    /// Render the rows in the requested format
+    pub fn render(&self, format: TableFormat) -> Result<String, ProcessError> {
+        if self.rows.is_empty() {
+            return Ok(String::new());
+        }
+
+        let mut text = match format {
+            TableFormat::Markdown => self.to_markdown(),
+            TableFormat::Csv => self.to_csv()?,
+            TableFormat::Json => self.to_json(),
+        };
+
+        if self.shown.0 < self.total.0 {
+            text.push_str(&format!("\n[truncated: showing {} of {} rows]", self.shown.0, self.total.0));
+        }
+        if self.shown.1 < self.total.1 {
+            text.push_str(&format!("\n[truncated: showing {} of {} columns]", self.shown.1, self.total.1));
+        }
+
+        Ok(text)
+    }
+
+    /// Column labels from the header row, falling back to spreadsheet column letters
+    pub fn labels(&self) -> Vec<String> {
+        (0..self.width())
+            .map(|col| {
+                let label = if self.has_header { cell_text(&self.rows[0][col]) } else { String::new() };
+                if label.trim().is_empty() {
+                    column_name(col)
+                } else {
+                    label.trim().to_string()
+                }
+            })
+            .collect()
+    }
+
+    fn body(&self) -> &[Vec<DataType>] {
+        if self.has_header {
+            &self.rows[1..]
+        } else {
+            &self.rows
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let labels = self.labels();
+        let mut text = markdown_row(labels.iter().map(|label| escape_markdown(label)));
+        text.push_str(&markdown_row(labels.iter().map(|_| "---".to_string())));
+        for row in self.body() {
+            text.push_str(&markdown_row(row.iter().map(|cell| escape_markdown(&cell_text(cell)))));
+        }
+        text
+    }
+
+    fn to_csv(&self) -> Result<String, ProcessError> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for row in &self.rows {
+            writer.write_record(row.iter().map(cell_text))
+                .map_err(|e| ProcessError::ProcessingFailed(e.to_string()))?;
+        }
+        let bytes = writer.into_inner()
+            .map_err(|e| ProcessError::ProcessingFailed(e.to_string()))?;
+        String::from_utf8(bytes).map_err(|e| ProcessError::ProcessingFailed(e.to_string()))
+    }
+
+    // Records are written by hand so keys keep the column order
+    fn to_json(&self) -> String {
+        let labels = self.labels();
+        let records: Vec<String> = self.body()
+            .iter()
+            .map(|row| {
+                let fields: Vec<String> = labels.iter()
+                    .zip(row)
+                    .map(|(label, cell)| format!("{}: {}", Value::from(label.as_str()), cell_json(cell)))
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+        format!("[\n{}\n]", records.join(",\n"))
+    }
+}
+
+/// Indices of the rows to keep out of `total` when at most `limit` fit
+pub(crate) fn sample_rows(total: usize, limit: usize, sampling: RowSampling) -> Vec<usize> {
+    let limit = limit.max(1);
+    if total <= limit {
+        return (0..total).collect();
+    }
+
+    match sampling {
+        RowSampling::Head => (0..limit).collect(),
+        RowSampling::HeadTail => {
+            let head = limit.div_ceil(2);
+            (0..head).chain(total - (limit - head)..total).collect()
+        }
+        RowSampling::Even if limit == 1 => vec![0],
+        RowSampling::Even => (0..limit).map(|i| i * (total - 1) / (limit - 1)).collect(),
+    }
+}
+
+/// Plain text for a cell, empty for blanks
+pub(crate) fn cell_text(cell: &DataType) -> String {
+    match cell {
+        DataType::String(s) | DataType::DateTimeIso(s) | DataType::DurationIso(s) => s.clone(),
+        DataType::Float(f) => f.to_string(),
+        DataType::Int(i) => i.to_string(),
+        DataType::Bool(b) => b.to_string(),
+        DataType::DateTime(dt) => dt.to_string(),
+        DataType::Duration(d) => d.to_string(),
+        DataType::Error(e) => e.to_string(),
+        DataType::Empty => String::new(),
+    }
+}
+
+fn cell_json(cell: &DataType) -> Value {
+    match cell {
+        DataType::Int(i) => Value::from(*i),
+        DataType::Float(f) => Value::from(*f),
+        DataType::Bool(b) => Value::from(*b),
+        DataType::Empty => Value::Null,
+        _ => Value::from(cell_text(cell)),
+    }
+}
+
+/// A Markdown table of text cells with the first row as its header, padding ragged rows
+pub(crate) fn markdown_table(rows: &[Vec<String>]) -> String {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let Some((header, body)) = rows.split_first().filter(|_| width > 0) else {
+        return String::new();
+    };
+
+    let cells = |row: &[String]| -> Vec<String> {
+        (0..width).map(|col| row.get(col).map_or(String::new(), |text| escape_markdown(text))).collect()
+    };
+    let mut text = markdown_row(cells(header).into_iter());
+    text.push_str(&markdown_row((0..width).map(|_| "---".to_string())));
+    for row in body {
+        text.push_str(&markdown_row(cells(row).into_iter()));
+    }
+    text
+}
+
+/// A table nested inside another table's cell, flattened to one line: cells split by " | ", rows by "; "
+pub(crate) fn flatten_table(rows: &[Vec<String>]) -> String {
+    let rows: Vec<String> = rows.iter().map(|row| row.join(" | ")).collect();
+    rows.join("; ")
+}
+
+/// Append a paragraph to a table cell's text, space separated
+pub(crate) fn push_line(cell: &mut String, text: &str) {
+    if !cell.is_empty() {
+        cell.push(' ');
+    }
+    cell.push_str(text);
+}
+
+fn markdown_row(cells: impl Iterator<Item = String>) -> String {
+    let cells: Vec<String> = cells.collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Spreadsheet-style column name for a zero-based index: A, B, ..., Z, AA, AB, ...
+pub(crate) fn column_name(mut col: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (col % 26) as u8);
+        if col < 26 {
+            break;
+        }
+        col = col / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+pub(crate) fn is_blank(cell: &DataType) -> bool {
+    match cell {
+        DataType::Empty => true,
+        DataType::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+fn is_numeric(cell: &DataType) -> bool {
+    match cell {
+        DataType::Int(_) | DataType::Float(_) => true,
+        DataType::String(s) => s.trim().parse::<f64>().is_ok(),
+        _ => false,
+    }
+}
+
+/// Pad ragged rows and drop empty rows and columns around the data, returning the columns dropped on the left
+fn trim_empty(mut rows: Vec<Vec<DataType>>) -> (Vec<Vec<DataType>>, usize) {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    for row in &mut rows {
+        row.resize(width, DataType::Empty);
+    }
+
+    let filled_row = |row: &Vec<DataType>| !row.iter().all(is_blank);
+    let (Some(first_row), Some(last_row)) = (rows.iter().position(filled_row), rows.iter().rposition(filled_row)) else {
+        return (Vec::new(), 0);
+    };
+    rows.truncate(last_row + 1);
+    rows.drain(..first_row);
+
+    let filled_col = |col: &usize| rows.iter().any(|row| !is_blank(&row[*col]));
+    let first_col = (0..width).find(filled_col).unwrap_or(0);
+    let last_col = (0..width).rev().find(filled_col).unwrap_or(0);
+    for row in &mut rows {
+        row.truncate(last_col + 1);
+        row.drain(..first_col);
+    }
+
+    (rows, first_col)
+}
+
+/// The first row is a header when it holds distinct text labels over a column of numbers,
+/// or, for all-text data, when none of its labels reappear in their own column
+fn detect_header(rows: &[Vec<DataType>]) -> bool {
+    let Some((first, rest)) = rows.split_first() else {
+        return false;
+    };
+
+    let mut labels = HashSet::new();
+    let distinct_labels = first.iter().all(|cell| match cell {
+        DataType::String(s) => !s.trim().is_empty() && !is_numeric(cell) && labels.insert(s.trim().to_lowercase()),
+        _ => false,
+    });
+    if !distinct_labels || rest.is_empty() {
+        return false;
+    }
+
+    let sample = &rest[..rest.len().min(HEADER_SNIFF_ROWS)];
+    let numeric_column = (0..first.len()).any(|col| {
+        sample.iter().any(|row| row.get(col).is_some_and(is_numeric))
+    });
+    if numeric_column {
+        return true;
+    }
+
+    first.iter().enumerate().all(|(col, label)| {
+        let label = cell_text(label).trim().to_lowercase();
+        !sample.iter().any(|row| row.get(col).is_some_and(|cell| cell_text(cell).trim().to_lowercase() == label))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(cells: &[&str]) -> Vec<DataType> {
+        cells.iter()
+            .map(|c| if c.is_empty() { DataType::Empty } else { DataType::String(c.to_string()) })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_header() {
+        assert!(detect_header(&[row(&["name", "qty"]), row(&["apple", "3"])]));
+        assert!(!detect_header(&[row(&["1", "2"]), row(&["3", "4"])]));
+        assert!(!detect_header(&[row(&["a", "a"]), row(&["x", "1"])]));
+        assert!(detect_header(&[row(&["name", "city"]), row(&["alice", "paris"]), row(&["bob", ""])]));
+        assert!(!detect_header(&[row(&["red", "left"]), row(&["blue", "left"])]));
+    }
+
+    #[test]
+    fn test_trim_and_render() {
+        let table = SheetTable::new("Sheet1", vec![
+            row(&["", "", ""]),
+            row(&["", "name", "qty", ""]),
+            row(&["", "a|b", ""]),
+            row(&["", "", "2"]),
+            row(&[""]),
+        ], (5, 4));
+        assert!(table.has_header);
+        assert_eq!(table.width(), 2);
+
+        assert_eq!(
+            table.render(TableFormat::Markdown).unwrap(),
+            "| name | qty |\n| --- | --- |\n| a\\|b |  |\n|  | 2 |\n"
+        );
+        assert_eq!(table.render(TableFormat::Csv).unwrap(), "name,qty\na|b,\n,2\n");
+        assert_eq!(
+            table.render(TableFormat::Json).unwrap(),
+            "[\n  {\"name\": \"a|b\", \"qty\": null},\n  {\"name\": null, \"qty\": \"2\"}\n]"
+        );
+    }
+
+    #[test]
+    fn test_truncation_marker() {
+        let table = SheetTable::new("Sheet1", vec![row(&["1"]), row(&["2"])], (10, 3));
+        assert_eq!(
+            table.render(TableFormat::Csv).unwrap(),
+            "1\n2\n\n[truncated: showing 2 of 10 rows]\n[truncated: showing 1 of 3 columns]"
+        );
+    }
+
+    #[test]
+    fn test_sample_rows() {
+        assert_eq!(sample_rows(3, 5, RowSampling::Even), vec![0, 1, 2]);
+        assert_eq!(sample_rows(10, 4, RowSampling::Head), vec![0, 1, 2, 3]);
+        assert_eq!(sample_rows(10, 5, RowSampling::HeadTail), vec![0, 1, 2, 8, 9]);
+        assert_eq!(sample_rows(10, 4, RowSampling::Even), vec![0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+    }
+}
@@ -0,0 +1,739 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use calamine::{CellErrorType, DataType};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use zip::ZipArchive;
+use crate::types::ProcessError;
+use super::package::{attribute, part_relationships, read_part, resolve_target};
+
+const CURRENCY_SYMBOLS: [char; 6] = ['$', '€', '£', '¥', '₹', '₩'];
+
+/// Days from the 1900 date system's epoch to the 1904 system's
+const DAYS_1900_TO_1904: f64 = 1462.0;
+
+/// Number formats that calamine reads as plain floats
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum NumberFormat {
+    Percent { decimals: usize },
+    Currency { symbol: String, decimals: usize },
+    Number { decimals: usize, thousands: bool },
+}
+
+/// How a cell style shows numbers
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CellStyle {
+    General,
+    Date,
+    Duration,
+    Number(NumberFormat),
+}
+
+/// Zero-based, inclusive (start, end) corners of a block of cells
+pub(crate) type CellRange = ((u32, u32), (u32, u32));
+
+/// Formatting and layout of one sheet, with cells keyed by zero-based (row, col)
+#[derive(Default)]
+pub(crate) struct SheetDetails {
+    pub formats: HashMap<(u32, u32), NumberFormat>,
+    pub merged: Vec<CellRange>,
+    pub hidden_rows: HashSet<u32>,
+    pub hidden_cols: HashSet<u32>,
+}
+
+/// Cell values kept while streaming a sheet, by zero-based (row, col)
+pub(crate) type SheetCells = HashMap<(u32, u32), DataType>;
+
+/// Called with each row's cells while a sheet is streamed, along with the layout read so far
+pub(crate) type RowObserver<'a> = dyn FnMut(u32, &[(u32, DataType)], &SheetDetails) + 'a;
+
+/// A `<c>` element whose value has not been read yet
+struct PendingCell {
+    position: (u32, u32),
+    kind: Option<String>,
+    style: CellStyle,
+}
+
+/// A cell comment or note
+pub(crate) struct CellComment {
+    pub position: (u32, u32),
+    pub author: Option<String>,
+    pub text: String,
+}
+
+/// Parts of an xlsx/xlsm package that calamine does not expose
+pub(crate) struct XlsxPackage {
+    archive: ZipArchive<File>,
+    sheet_paths: HashMap<String, String>,
+    styles: Vec<CellStyle>,
+    date1904: bool,
+    shared_strings: Option<Vec<String>>,
+}
+
+impl XlsxPackage {
+    pub fn open(path: &Path) -> Result<Self, ProcessError> {
+        let file = File::open(path)?;
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+
+        let workbook = read_part(&mut archive, "xl/workbook.xml")?
+            .ok_or_else(|| ProcessError::InvalidFormat("Missing xl/workbook.xml".to_string()))?;
+        let (sheet_ids, date1904) = parse_workbook(&workbook)?;
+        let relationships = part_relationships(&mut archive, "xl/workbook.xml")?;
+        let sheet_paths = sheet_ids.into_iter()
+            .filter_map(|(name, id)| {
+                relationships.get(&id).map(|rel| (name, resolve_target("xl/workbook.xml", &rel.target)))
+            })
+            .collect();
+
+        let styles = match read_part(&mut archive, "xl/styles.xml")? {
+            Some(styles) => parse_styles(&styles)?,
+            None => Vec::new(),
+        };
+
+        Ok(XlsxPackage { archive, sheet_paths, styles, date1904, shared_strings: None })
+    }
+
+    /// The used range a sheet declares in its `<dimension>` element, read without loading the sheet
+    pub fn dimension(&mut self, sheet: &str) -> Result<Option<CellRange>, ProcessError> {
+        let Some(part) = self.sheet_paths.get(sheet) else {
+            return Ok(None);
+        };
+        let Ok(entry) = self.archive.by_name(part) else {
+            return Ok(None);
+        };
+
+        let mut reader = Reader::from_reader(BufReader::new(entry));
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                    b"dimension" => return Ok(attribute(e, b"ref").and_then(|r| parse_range_ref(&r))),
+                    b"sheetData" => return Ok(None),
+                    _ => (),
+                },
+                Ok(Event::Eof) => return Ok(None),
+                Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+                _ => (),
+            }
+            buf.clear();
+        }
+    }
+
+    /// Number formats, merged cells and hidden rows and columns of a sheet
+    pub fn sheet_details(&mut self, sheet: &str) -> Result<SheetDetails, ProcessError> {
+        self.scan_sheet(sheet, None, None).map(|(details, _)| details)
+    }
+
+    /// Stream a sheet, keeping the values of the `keep` rows and showing every row to `observe`
+    ///
+    /// Without an observer the rest of the sheet data is skipped after the last kept row, so only
+    /// the kept rows are ever held in memory.
+    pub fn stream_sheet(
+        &mut self,
+        sheet: &str,
+        keep: &BTreeSet<u32>,
+        observe: Option<&mut RowObserver>,
+    ) -> Result<(SheetDetails, SheetCells), ProcessError> {
+        self.load_shared_strings()?;
+        self.scan_sheet(sheet, Some(keep), observe)
+    }
+
+    fn scan_sheet(
+        &mut self,
+        sheet: &str,
+        keep: Option<&BTreeSet<u32>>,
+        mut observe: Option<&mut RowObserver>,
+    ) -> Result<(SheetDetails, SheetCells), ProcessError> {
+        let mut details = SheetDetails::default();
+        let mut cells = HashMap::new();
+        let Some(part) = self.sheet_paths.get(sheet) else {
+            return Ok((details, cells));
+        };
+        let Ok(entry) = self.archive.by_name(part) else {
+            return Ok((details, cells));
+        };
+        let styles = &self.styles;
+        let shared_strings = self.shared_strings.as_deref().unwrap_or_default();
+        let date1904 = self.date1904;
+        let last_kept = keep.and_then(|keep| keep.last().copied());
+
+        let mut reader = Reader::from_reader(BufReader::new(entry));
+        let mut buf = Vec::new();
+        let (mut row, mut next_row, mut next_col) = (0, 0, 0);
+        let mut row_kept = false;
+        // Past the last kept row only hidden rows and the merged cells after the sheet data matter
+        let mut past_kept = false;
+        let mut row_cells: Vec<(u32, DataType)> = Vec::new();
+        let mut pending: Option<PendingCell> = None;
+        let mut value = String::new();
+        let mut in_value = false;
+        loop {
+            let event = reader.read_event_into(&mut buf);
+            // An empty `<c/>` has no value to wait for
+            let has_content = matches!(event, Ok(Event::Start(_)));
+            match event {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                    b"row" => {
+                        row = attribute(e, b"r")
+                            .and_then(|r| r.parse::<u32>().ok())
+                            .map_or(next_row, |r| r.saturating_sub(1));
+                        next_row = row + 1;
+                        next_col = 0;
+                        row_kept = keep.is_some_and(|keep| keep.contains(&row));
+                        row_cells.clear();
+                        if is_hidden(e) {
+                            details.hidden_rows.insert(row);
+                        }
+                    }
+                    b"c" if past_kept => (),
+                    b"c" => {
+                        let position = attribute(e, b"r")
+                            .and_then(|r| parse_cell_ref(&r))
+                            .unwrap_or((row, next_col));
+                        next_col = position.1 + 1;
+                        let style = attribute(e, b"s")
+                            .and_then(|s| s.parse::<usize>().ok())
+                            .and_then(|s| styles.get(s).cloned())
+                            .unwrap_or(CellStyle::General);
+                        if keep.is_none() || row_kept {
+                            if let CellStyle::Number(format) = &style {
+                                details.formats.insert(position, format.clone());
+                            }
+                        }
+                        // Values are only parsed for rows someone will look at
+                        if has_content && (row_kept || observe.is_some()) {
+                            pending = Some(PendingCell { position, kind: attribute(e, b"t"), style });
+                            value.clear();
+                        }
+                    }
+                    b"v" | b"t" => in_value = pending.is_some(),
+                    b"col" if is_hidden(e) => {
+                        let min = attribute(e, b"min").and_then(|min| min.parse::<u32>().ok());
+                        let max = attribute(e, b"max").and_then(|max| max.parse::<u32>().ok());
+                        if let (Some(min), Some(max)) = (min, max) {
+                            details.hidden_cols.extend(min.saturating_sub(1)..max);
+                        }
+                    }
+                    b"mergeCell" => {
+                        if let Some(range) = attribute(e, b"ref").and_then(|r| parse_range_ref(&r)) {
+                            details.merged.push(range);
+                        }
+                    }
+                    _ => (),
+                },
+                Ok(Event::Text(e)) if in_value => {
+                    let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                    value.push_str(&text);
+                }
+                Ok(Event::End(ref e)) => match e.name().as_ref() {
+                    b"v" | b"t" => in_value = false,
+                    b"c" => {
+                        if let Some(cell) = pending.take() {
+                            let data = cell_value(cell.kind.as_deref(), std::mem::take(&mut value), &cell.style, shared_strings, date1904);
+                            row_cells.push((cell.position.1, data));
+                        }
+                    }
+                    b"row" => {
+                        if let Some(observe) = observe.as_mut() {
+                            observe(row, &row_cells, &details);
+                        }
+                        if row_kept {
+                            cells.extend(row_cells.drain(..).map(|(col, data)| ((row, col), data)));
+                        }
+                        if observe.is_none() && last_kept.is_some_and(|last| row >= last) {
+                            past_kept = true;
+                        }
+                    }
+                    _ => (),
+                },
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+                _ => (),
+            }
+            buf.clear();
+        }
+
+        Ok((details, cells))
+    }
+
+    /// Excel tables (ListObjects) on a sheet, by display name
+    pub fn tables(&mut self, sheet: &str) -> Result<Vec<(String, CellRange)>, ProcessError> {
+        let Some(part) = self.sheet_paths.get(sheet).cloned() else {
+            return Ok(Vec::new());
+        };
+        let relationships = part_relationships(&mut self.archive, &part)?;
+        let mut table_parts: Vec<String> = relationships.values()
+            .filter(|rel| rel.kind.ends_with("/table"))
+            .map(|rel| resolve_target(&part, &rel.target))
+            .collect();
+        table_parts.sort();
+
+        let mut tables = Vec::new();
+        for table_part in table_parts {
+            if let Some(table) = read_part(&mut self.archive, &table_part)?.and_then(|content| parse_table(&content)) {
+                tables.push(table);
+            }
+        }
+        Ok(tables)
+    }
+
+    fn load_shared_strings(&mut self) -> Result<(), ProcessError> {
+        if self.shared_strings.is_some() {
+            return Ok(());
+        }
+        let strings = match self.archive.by_name("xl/sharedStrings.xml") {
+            Ok(entry) => parse_shared_strings(&mut Reader::from_reader(BufReader::new(entry)))?,
+            Err(_) => Vec::new(),
+        };
+        self.shared_strings = Some(strings);
+        Ok(())
+    }
+
+    /// Comments and notes attached to the cells of a sheet, in sheet order
+    pub fn comments(&mut self, sheet: &str) -> Result<Vec<CellComment>, ProcessError> {
+        let Some(part) = self.sheet_paths.get(sheet).cloned() else {
+            return Ok(Vec::new());
+        };
+        let relationships = part_relationships(&mut self.archive, &part)?;
+        let Some(comments_part) = relationships.values()
+            .find(|rel| rel.kind.ends_with("/comments"))
+            .map(|rel| resolve_target(&part, &rel.target)) else {
+            return Ok(Vec::new());
+        };
+        let Some(content) = read_part(&mut self.archive, &comments_part)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut reader = Reader::from_str(&content);
+        let mut buf = Vec::new();
+        let mut authors: Vec<String> = Vec::new();
+        let mut comments = Vec::new();
+        let mut position: Option<(u32, u32)> = None;
+        let mut author: Option<usize> = None;
+        let mut text = String::new();
+        let mut in_author = false;
+        let mut in_text = false;
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(ref e)) => match e.name().as_ref() {
+                    b"author" => {
+                        in_author = true;
+                        authors.push(String::new());
+                    }
+                    b"comment" => {
+                        position = attribute(e, b"ref").and_then(|r| parse_cell_ref(&r));
+                        author = attribute(e, b"authorId").and_then(|id| id.parse().ok());
+                        text.clear();
+                    }
+                    b"t" => in_text = true,
+                    _ => (),
+                },
+                Ok(Event::Text(e)) if in_author || in_text => {
+                    let value = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                    match authors.last_mut() {
+                        Some(author) if in_author => author.push_str(&value),
+                        _ => text.push_str(&value),
+                    }
+                }
+                Ok(Event::End(ref e)) => match e.name().as_ref() {
+                    b"author" => in_author = false,
+                    b"t" => in_text = false,
+                    b"comment" => {
+                        if let Some(position) = position.take() {
+                            comments.push(CellComment {
+                                position,
+                                author: author.take().and_then(|id| authors.get(id)).filter(|a| !a.is_empty()).cloned(),
+                                text: text.trim().to_string(),
+                            });
+                        }
+                    }
+                    _ => (),
+                },
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+                _ => (),
+            }
+            buf.clear();
+        }
+
+        comments.sort_by_key(|comment| comment.position);
+        Ok(comments)
+    }
+}
+
+impl NumberFormat {
+    /// Render a value the way Excel displays it with this format
+    pub fn apply(&self, value: f64) -> String {
+        match self {
+            NumberFormat::Percent { decimals } => format!("{:.*}%", decimals, value * 100.0),
+            NumberFormat::Currency { symbol, decimals } => {
+                let sign = if value < 0.0 { "-" } else { "" };
+                format!("{}{}{}", sign, symbol, group_thousands(&format!("{:.*}", decimals, value.abs())))
+            }
+            NumberFormat::Number { decimals, thousands: true } => group_thousands(&format!("{:.*}", decimals, value)),
+            NumberFormat::Number { decimals, thousands: false } => format!("{:.*}", decimals, value),
+        }
+    }
+}
+
+fn is_hidden(element: &BytesStart) -> bool {
+    matches!(attribute(element, b"hidden").as_deref(), Some("1") | Some("true"))
+}
+
+/// The value of a `<c>` element from its `t` attribute, raw text and style
+///
+/// Date serials are moved to the 1900 date system, as calamine does, so both readers agree.
+fn cell_value(kind: Option<&str>, value: String, style: &CellStyle, shared_strings: &[String], date1904: bool) -> DataType {
+    match kind {
+        Some("s") => value.trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| shared_strings.get(index))
+            .map_or(DataType::Empty, |text| DataType::String(text.clone())),
+        Some("str") | Some("inlineStr") => DataType::String(value),
+        Some("b") => DataType::Bool(value.trim() == "1"),
+        Some("d") => DataType::DateTimeIso(value),
+        Some("e") => match value.trim() {
+            "#DIV/0!" => DataType::Error(CellErrorType::Div0),
+            "#N/A" => DataType::Error(CellErrorType::NA),
+            "#NAME?" => DataType::Error(CellErrorType::Name),
+            "#NULL!" => DataType::Error(CellErrorType::Null),
+            "#NUM!" => DataType::Error(CellErrorType::Num),
+            "#REF!" => DataType::Error(CellErrorType::Ref),
+            "#VALUE!" => DataType::Error(CellErrorType::Value),
+            _ => DataType::String(value),
+        },
+        _ if value.is_empty() => DataType::Empty,
+        _ => match (value.trim().parse::<f64>(), style) {
+            (Ok(number), CellStyle::Date) if date1904 => DataType::DateTime(number + DAYS_1900_TO_1904),
+            (Ok(number), CellStyle::Date) => DataType::DateTime(number),
+            (Ok(number), CellStyle::Duration) => DataType::Duration(number),
+            (Ok(number), _) => DataType::Float(number),
+            (Err(_), _) => DataType::String(value),
+        },
+    }
+}
+
+/// The text of each `<si>` item, skipping phonetic runs
+fn parse_shared_strings<R: BufRead>(reader: &mut Reader<R>) -> Result<Vec<String>, ProcessError> {
+    let mut buf = Vec::new();
+    let mut strings: Vec<String> = Vec::new();
+    let mut in_text = false;
+    let mut in_phonetic = false;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) => match e.name().as_ref() {
+                b"si" => strings.push(String::new()),
+                b"rPh" => in_phonetic = true,
+                b"t" => in_text = !in_phonetic,
+                _ => (),
+            },
+            Ok(Event::Empty(ref e)) if e.name().as_ref() == b"si" => strings.push(String::new()),
+            Ok(Event::Text(e)) if in_text => {
+                let text = e.unescape().map_err(|e| ProcessError::ExtractionFailed(e.to_string()))?;
+                if let Some(string) = strings.last_mut() {
+                    string.push_str(&text);
+                }
+            }
+            Ok(Event::End(ref e)) => match e.name().as_ref() {
+                b"rPh" => in_phonetic = false,
+                b"t" => in_text = false,
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(strings)
+}
+
+/// Name and range of a table part's `<table>` element
+fn parse_table(content: &str) -> Option<(String, CellRange)> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) if e.name().as_ref() == b"table" => {
+                let name = attribute(e, b"displayName").or_else(|| attribute(e, b"name"))?;
+                let range = attribute(e, b"ref").and_then(|r| parse_range_ref(&r))?;
+                return Some((name, range));
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+/// Corners of an `A1:C3` style reference, ignoring `$` anchors; a single cell is a 1x1 range
+pub(crate) fn parse_range_ref(reference: &str) -> Option<CellRange> {
+    let reference = reference.replace('$', "");
+    match reference.split_once(':') {
+        Some((start, end)) => Some((parse_cell_ref(start)?, parse_cell_ref(end)?)),
+        None => parse_cell_ref(&reference).map(|cell| (cell, cell)),
+    }
+}
+
+/// Zero-based (row, col) for an A1-style reference
+pub(crate) fn parse_cell_ref(reference: &str) -> Option<(u32, u32)> {
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() {
+        return None;
+    }
+    let col = letters.bytes().try_fold(0u32, |col, b| {
+        b.is_ascii_uppercase().then(|| col * 26 + (b - b'A') as u32 + 1)
+    })?;
+    let row: u32 = digits.parse().ok()?;
+    Some((row.checked_sub(1)?, col - 1))
+}
+
+/// Sheet names with their relationship ids, and whether dates use the 1904 system
+fn parse_workbook(content: &str) -> Result<(Vec<(String, String)>, bool), ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut sheets = Vec::new();
+    let mut date1904 = false;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                b"workbookPr" => {
+                    date1904 = matches!(attribute(e, b"date1904").as_deref(), Some("1") | Some("true"));
+                }
+                b"sheet" => {
+                    if let (Some(name), Some(id)) = (attribute(e, b"name"), attribute(e, b"r:id")) {
+                        sheets.push((name, id));
+                    }
+                }
+                _ => (),
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok((sheets, date1904))
+}
+
+/// How each cell style (`cellXfs` entry) shows numbers, by style index
+fn parse_styles(content: &str) -> Result<Vec<CellStyle>, ProcessError> {
+    let mut reader = Reader::from_str(content);
+    let mut buf = Vec::new();
+    let mut codes: HashMap<u32, String> = HashMap::new();
+    let mut styles = Vec::new();
+    let mut in_cell_xfs = false;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name().as_ref() {
+                b"numFmt" => {
+                    let id = attribute(e, b"numFmtId").and_then(|id| id.parse().ok());
+                    if let (Some(id), Some(code)) = (id, attribute(e, b"formatCode")) {
+                        codes.insert(id, code);
+                    }
+                }
+                b"cellXfs" => in_cell_xfs = true,
+                b"xf" if in_cell_xfs => {
+                    let id = attribute(e, b"numFmtId").and_then(|id| id.parse().ok()).unwrap_or(0);
+                    styles.push(cell_style(id, codes.get(&id).map(String::as_str)));
+                }
+                _ => (),
+            },
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"cellXfs" => in_cell_xfs = false,
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ProcessError::ExtractionFailed(e.to_string())),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(styles)
+}
+
+fn cell_style(id: u32, code: Option<&str>) -> CellStyle {
+    if let Some(style) = date_style(id, code) {
+        return style;
+    }
+    classify_format(id, code).map_or(CellStyle::General, CellStyle::Number)
+}
+
+/// Date and elapsed time formats, whose numbers are day serials
+fn date_style(id: u32, code: Option<&str>) -> Option<CellStyle> {
+    let Some(code) = code else {
+        return match id {
+            14..=22 | 27..=36 | 45 | 47 | 50..=58 => Some(CellStyle::Date),
+            46 => Some(CellStyle::Duration),
+            _ => None,
+        };
+    };
+
+    let mut literal = String::new();
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => chars.by_ref().take_while(|&c| c != '"').for_each(drop),
+            '\\' | '_' | '*' => {
+                chars.next();
+            }
+            '[' => {
+                // `[h]`, `[mm]` and `[ss]` count elapsed time past a day
+                let token: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if !token.is_empty() && token.chars().all(|c| matches!(c.to_ascii_lowercase(), 'h' | 'm' | 's')) {
+                    return Some(CellStyle::Duration);
+                }
+            }
+            c => literal.push(c.to_ascii_lowercase()),
+        }
+    }
+
+    literal.contains(['y', 'm', 'd', 'h', 's']).then_some(CellStyle::Date)
+}
+
+/// Classify a built-in format id or custom format code
+fn classify_format(id: u32, code: Option<&str>) -> Option<NumberFormat> {
+    match (id, code) {
+        (_, Some(code)) => classify_format_code(code),
+        (2, None) => Some(NumberFormat::Number { decimals: 2, thousands: false }),
+        (3 | 37 | 38, None) => Some(NumberFormat::Number { decimals: 0, thousands: true }),
+        (4 | 39 | 40, None) => Some(NumberFormat::Number { decimals: 2, thousands: true }),
+        (5 | 6, None) => Some(NumberFormat::Currency { symbol: "$".to_string(), decimals: 0 }),
+        (7 | 8, None) => Some(NumberFormat::Currency { symbol: "$".to_string(), decimals: 2 }),
+        (9, None) => Some(NumberFormat::Percent { decimals: 0 }),
+        (10, None) => Some(NumberFormat::Percent { decimals: 2 }),
+        _ => None,
+    }
+}
+
+fn classify_format_code(code: &str) -> Option<NumberFormat> {
+    // Only the positive section matters; quoted text and [..] tokens are not digits
+    let mut literal = String::new();
+    let mut symbol: Option<String> = None;
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => {
+                let quoted: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                symbol = symbol.or_else(|| quoted.chars().find(|c| CURRENCY_SYMBOLS.contains(c)).map(String::from));
+            }
+            '\\' | '_' | '*' => {
+                chars.next();
+            }
+            '[' => {
+                let token: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some(currency) = token.strip_prefix('$') {
+                    let currency = currency.split('-').next().unwrap_or("");
+                    if !currency.is_empty() {
+                        symbol = Some(currency.to_string());
+                    }
+                }
+            }
+            c if CURRENCY_SYMBOLS.contains(&c) => symbol = Some(c.to_string()),
+            c => literal.push(c),
+        }
+    }
+
+    // Dates, times, scientific notation and text are left as they are
+    if literal.chars().any(|c| matches!(c.to_ascii_lowercase(), 'y' | 'm' | 'd' | 'h' | 's' | 'e' | '@')) {
+        return None;
+    }
+
+    let decimals = literal.split_once('.')
+        .map(|(_, fraction)| fraction.chars().take_while(|c| matches!(c, '0' | '#' | '?')).count())
+        .unwrap_or(0);
+
+    if literal.contains('%') {
+        Some(NumberFormat::Percent { decimals })
+    } else if let Some(symbol) = symbol {
+        Some(NumberFormat::Currency { symbol, decimals })
+    } else if literal.contains(',') || decimals > 0 {
+        Some(NumberFormat::Number { decimals, thousands: literal.contains(',') })
+    } else {
+        None
+    }
+}
+
+fn group_thousands(number: &str) -> String {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", number),
+    };
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+
+    let mut grouped = String::new();
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    match fraction {
+        Some(fraction) => format!("{}{}.{}", sign, grouped, fraction),
+        None => format!("{}{}", sign, grouped),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_format() {
+        assert_eq!(classify_format(10, None), Some(NumberFormat::Percent { decimals: 2 }));
+        assert_eq!(classify_format(0, None), None);
+        assert_eq!(
+            classify_format(164, Some("[$€-407]#,##0.00;[Red]-[$€-407]#,##0.00")),
+            Some(NumberFormat::Currency { symbol: "€".to_string(), decimals: 2 })
+        );
+        assert_eq!(classify_format(165, Some("0.0%")), Some(NumberFormat::Percent { decimals: 1 }));
+        assert_eq!(classify_format(166, Some("yyyy-mm-dd")), None);
+        assert_eq!(classify_format(167, Some("\"£\"#,##0")), Some(NumberFormat::Currency { symbol: "£".to_string(), decimals: 0 }));
+    }
+
+    #[test]
+    fn test_date_style() {
+        assert_eq!(cell_style(14, None), CellStyle::Date);
+        assert_eq!(cell_style(46, None), CellStyle::Duration);
+        assert_eq!(cell_style(164, Some("yyyy-mm-dd\\ hh:mm")), CellStyle::Date);
+        assert_eq!(cell_style(165, Some("[h]:mm:ss")), CellStyle::Duration);
+        assert_eq!(cell_style(166, Some("[Red]0.00")), CellStyle::Number(NumberFormat::Number { decimals: 2, thousands: false }));
+        assert_eq!(cell_style(167, Some("\"Day\" 0")), CellStyle::General);
+    }
+
+    #[test]
+    fn test_cell_value() {
+        let shared = vec!["apple".to_string()];
+        assert_eq!(cell_value(Some("s"), "0".to_string(), &CellStyle::General, &shared, false), DataType::String("apple".to_string()));
+        assert_eq!(cell_value(None, "45000".to_string(), &CellStyle::Date, &shared, false), DataType::DateTime(45000.0));
+        assert_eq!(cell_value(None, "43661.5".to_string(), &CellStyle::Date, &shared, true), DataType::DateTime(45123.5));
+        assert_eq!(cell_value(Some("e"), "#N/A".to_string(), &CellStyle::General, &shared, false), DataType::Error(CellErrorType::NA));
+        assert_eq!(cell_value(None, String::new(), &CellStyle::General, &shared, false), DataType::Empty);
+
+        let xml = "<sst><si><t>a</t></si><si><r><t>b</t></r><r><t>c</t></r><rPh><t>x</t></rPh></si></sst>";
+        assert_eq!(parse_shared_strings(&mut Reader::from_str(xml)).unwrap(), vec!["a", "bc"]);
+    }
+
+    #[test]
+    fn test_apply_format() {
+        assert_eq!(NumberFormat::Percent { decimals: 1 }.apply(0.1234), "12.3%");
+        assert_eq!(NumberFormat::Currency { symbol: "$".to_string(), decimals: 2 }.apply(-1234567.5), "-$1,234,567.50");
+        assert_eq!(NumberFormat::Number { decimals: 0, thousands: true }.apply(999.0), "999");
+    }
+
+    #[test]
+    fn test_parse_cell_ref() {
+        assert_eq!(parse_cell_ref("A1"), Some((0, 0)));
+        assert_eq!(parse_cell_ref("AB12"), Some((11, 27)));
+        assert_eq!(parse_cell_ref("12"), None);
+        assert_eq!(parse_range_ref("$B$2:$D$10"), Some(((1, 1), (9, 3))));
+    }
+}
@@ -1,17 +1,19 @@
 use std::path::PathBuf;
 use std::fmt;
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
 pub const SUPPORTED_BASE_FILE_EXTENSIONS: &[&str] = &[
-    "csv", "txt",
+    "csv", "tsv", "txt",
     "doc", "docx", "docm", "odt", "rtf",
-    "xls", "xlsx", "xlsm", "ods",
+    "xls", "xlsx", "xlsm", "xlsb", "ods",
     "ppt", "pptx", "pptm", "odp",
     "html", "htm",
     "bmp", "gif", "jpg", "jpeg", "png", "tiff", "tif", "webp", "heic", "heif",
-    "pdf"
+    "pdf",
+    "xps", "oxps", "epub", "cbz", "cbr", "fb2", "mobi"
 ];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +27,89 @@ pub struct Config {
     pub threads: usize,
     pub timeout_seconds: u32,
     pub keep_temps: bool,
+    #[serde(default)]
+    pub pdf_renderer: PdfRendererKind,
+    /// Only process sheets whose names match one of these patterns (`*` and `?` wildcards)
+    #[serde(default)]
+    pub sheet_include: Vec<String>,
+    /// Skip sheets whose names match any of these patterns
+    #[serde(default)]
+    pub sheet_exclude: Vec<String>,
+    /// Skip sheets marked hidden or very hidden in the workbook
+    #[serde(default = "default_true")]
+    pub skip_hidden_sheets: bool,
+    /// Skip rows and columns hidden in the sheet
+    #[serde(default = "default_true")]
+    pub skip_hidden_cells: bool,
+    #[serde(default)]
+    pub table_format: TableFormat,
+    #[serde(default)]
+    pub row_sampling: RowSampling,
+    /// Add a part per sheet listing cell formulas and comments
+    #[serde(default)]
+    pub sheet_annotations: bool,
+    /// Most formulas and comments listed per sheet
+    #[serde(default = "default_max_annotations")]
+    pub max_annotations: u32,
+    /// Add a column profile per sheet ahead of its rows
+    #[serde(default)]
+    pub sheet_summary: bool,
+    /// How tracked insertions and deletions in Word documents are shown
+    #[serde(default)]
+    pub tracked_changes: TrackedChanges,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_annotations() -> u32 {
+    500
+}
+
+/// Backend used to open and render PDF pages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfRendererKind {
+    #[default]
+    Mupdf,
+    Poppler,
+}
+
+/// How spreadsheet rows are rendered in the prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    #[default]
+    Markdown,
+    Csv,
+    Json,
+}
+
+/// Which rows to keep when a sheet has more than `max_rows`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowSampling {
+    /// The first rows
+    #[default]
+    Head,
+    /// Half from the start and half from the end
+    HeadTail,
+    /// Evenly spaced rows, always including the first and last
+    Even,
+}
+
+/// Which revisions of a Word document with tracked changes to read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackedChanges {
+    /// The final text, with every change accepted
+    #[default]
+    Accept,
+    /// The original text, with every change rejected
+    Reject,
+    /// Both, with insertions and deletions marked along with their author
+    Markup,
 }
 
 impl Default for Config {
@@ -39,6 +124,17 @@ impl Default for Config {
             threads: num_cpus::get(),
             timeout_seconds: 300,  // 5 minutes default
             keep_temps: false,
+            pdf_renderer: PdfRendererKind::Mupdf,
+            sheet_include: Vec::new(),
+            sheet_exclude: Vec::new(),
+            skip_hidden_sheets: true,
+            skip_hidden_cells: true,
+            table_format: TableFormat::Markdown,
+            row_sampling: RowSampling::Head,
+            sheet_annotations: false,
+            max_annotations: default_max_annotations(),
+            sheet_summary: false,
+            tracked_changes: TrackedChanges::Accept,
         }
     }
 }
@@ -48,6 +144,7 @@ pub enum Strategy {
     Text,
     Spreadsheet,
     PDF,
+    PagedDocument,
     Office,
     Image,
 }
@@ -58,6 +155,7 @@ impl fmt::Display for Strategy {
             Strategy::Text => write!(f, "text"),
             Strategy::Spreadsheet => write!(f, "spreadsheet"),
             Strategy::PDF => write!(f, "pdf"),
+            Strategy::PagedDocument => write!(f, "paged"),
             Strategy::Office => write!(f, "office"),
             Strategy::Image => write!(f, "image"),
         }
@@ -71,11 +169,14 @@ impl Strategy {
             "txt" | "html" | "htm" => Strategy::Text,
             
             // Spreadsheets
-            "csv" | "xls" | "xlsx" | "xlsm" | "ods" => Strategy::Spreadsheet,
+            "csv" | "tsv" | "xls" | "xlsx" | "xlsm" | "xlsb" | "ods" => Strategy::Spreadsheet,
             
             // PDF files
             "pdf" => Strategy::PDF,
             
+            // Other paged documents mupdf can open
+            "xps" | "oxps" | "epub" | "cbz" | "cbr" | "fb2" | "mobi" => Strategy::PagedDocument,
+            
             // Office documents
             "doc" | "docx" | "docm" | "odt" | "rtf" |
             "ppt" | "pptx" | "pptm" | "odp" => Strategy::Office,
@@ -158,6 +259,7 @@ pub struct QueryMetadataOutput {
     pub original_file_size: i64,
     pub errors: Vec<String>,
     pub steps: Vec<ProcessingStepOutput>,
+    pub properties: HashMap<String, String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -201,6 +303,7 @@ impl From<crate::proto::processor::QueryMetadata> for QueryMetadataOutput {
             original_file_size: meta.original_file_size,
             errors: meta.errors,
             steps: meta.steps.into_iter().map(Into::into).collect(),
+            properties: meta.properties,
         }
     }
 }
@@ -45,7 +45,7 @@ fn validate_query_result(result: &Query, file_path: &Path) {
     assert_eq!(result.file_type, extension, "File type should match extension");
     
     match expected_strategy {
-        Strategy::Text | Strategy::Spreadsheet | Strategy::Office => {
+        Strategy::Text => {
             assert!(!result.prompt_parts.is_empty(), "Should have extracted text");
             for part in &result.prompt_parts {
                 assert!(
@@ -54,7 +54,35 @@ fn validate_query_result(result: &Query, file_path: &Path) {
                 );
             }
         }
-        Strategy::PDF => {
+        Strategy::Office => {
+            assert!(!result.prompt_parts.is_empty(), "Should have extracted text");
+            for part in &result.prompt_parts {
+                assert!(
+                    part.starts_with("<EXTRACTED_DATA>")
+                        || part.starts_with("<SLIDE ")
+                        || part.starts_with("<SPEAKER_NOTES>")
+                        || part.starts_with("<SECTION TYPE=")
+                        || (part.starts_with("<OCR PAGE=") && part.contains(" IMAGE=")),
+                    "Each part should be extracted text, a slide, speaker notes, a section or embedded image OCR"
+                );
+            }
+            if extension == "pptx" {
+                assert!(
+                    result.prompt_parts.iter().any(|part| part.starts_with("<SLIDE ")),
+                    "Presentations should have slide parts"
+                );
+            }
+        }
+        Strategy::Spreadsheet => {
+            assert!(!result.prompt_parts.is_empty(), "Should have extracted sheets");
+            for part in &result.prompt_parts {
+                assert!(
+                    part.starts_with("<SHEET") || part.starts_with("<TABLE"),
+                    "Each part should be a tagged sheet or table"
+                );
+            }
+        }
+        Strategy::PDF | Strategy::PagedDocument => {
             assert!(
                 !result.prompt_parts.is_empty() || !result.attachments.is_empty(),
                 "Should have either extracted text or images"
@@ -89,7 +117,14 @@ async fn test_text_files() {
 #[tokio::test]
 async fn test_spreadsheet_files() {
     let mut processor = create_full_processor();
-    let test_files = ["spreadsheet-test-1.xlsx", "spreadsheet-test-2.csv"];
+    let test_files = [
+        "spreadsheet-test-1.xlsx",
+        "spreadsheet-test-2.csv",
+        "spreadsheet-test-3.xls",
+        "spreadsheet-test-4.xlsm",
+        "spreadsheet-test-5.xlsb",
+        "spreadsheet-test-6.ods",
+    ];
 
     for file_name in test_files {
         let file_path = PathBuf::from("test_data").join(file_name);
@@ -114,6 +149,20 @@ async fn test_pdf_files() {
     }
 }
 
+#[tokio::test]
+async fn test_paged_document_files() {
+    let mut processor = create_full_processor();
+    let test_files = ["paged-test-1.epub", "paged-test-2.xps"];
+
+    for file_name in test_files {
+        let file_path = PathBuf::from("test_data").join(file_name);
+        let mut query = create_test_query(&file_path);
+        
+        let result = processor.process(&mut query).await.unwrap();
+        validate_query_result(&result, &file_path);
+    }
+}
+
 #[tokio::test]
 async fn test_office_files() {
     let mut processor = create_full_processor();
@@ -167,6 +216,7 @@ async fn test_file_metadata() {
         original_file_size: std::fs::metadata(&file_path).unwrap().len() as i64,
         errors: Vec::new(),
         steps: Vec::new(),
+        properties: Default::default(),
     });
     
     // Add a small delay to ensure started_at and completed_at are different